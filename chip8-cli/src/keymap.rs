@@ -0,0 +1,80 @@
+// Which PS/2 scancode (set 1) stands for each key of the CHIP-8 hex keypad.
+
+/// The layout of the physical keyboard, to turn the labels of a keymap into scancodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardLayout {
+    Qwerty,
+    Azerty,
+}
+
+// the three letter rows of a keyboard, in scancode order
+static QWERTY_ROWS: [(&str, u8); 3] = [("qwertyuiop", 0x10), ("asdfghjkl", 0x1E), ("zxcvbnm", 0x2C)];
+static AZERTY_ROWS: [(&str, u8); 3] = [("azertyuiop", 0x10), ("qsdfghjklm", 0x1E), ("wxcvbn", 0x2C)];
+
+impl KeyboardLayout {
+    /// Scancode of the key labelled `label`, digits are on the top row on both layouts.
+    pub fn scancode(&self, label: char) -> Option<u8> {
+        let label = label.to_ascii_lowercase();
+        match label {
+            '1'..='9' => return Some(label as u8 - b'1' + 0x02),
+            '0' => return Some(0x0B),
+            _ => {}
+        }
+        let rows = match self {
+            KeyboardLayout::Qwerty => &QWERTY_ROWS,
+            KeyboardLayout::Azerty => &AZERTY_ROWS,
+        };
+        rows.iter().find_map(|(row, first)| {
+            row.chars().position(|c| c == label).map(|pos| first + pos as u8)
+        })
+    }
+}
+
+/// Scancode of each CHIP-8 key, indexed by the key value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keymap {
+    scancodes: [u8; 16],
+}
+
+/// Order of the keys on the CHIP-8 keypad, left to right and top to bottom.
+static KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC,
+    0x4, 0x5, 0x6, 0xD,
+    0x7, 0x8, 0x9, 0xE,
+    0xA, 0x0, 0xB, 0xF,
+];
+
+impl Keymap {
+    /// Builds a keymap from 16 key labels, given in the order of the CHIP-8 keypad
+    /// (`123C 456D 789E A0BF`). Returns None if a label has no key on that layout.
+    pub fn from_labels(labels: &str, layout: KeyboardLayout) -> Option<Keymap> {
+        if labels.chars().count() != 16 {
+            return None;
+        }
+        let mut scancodes = [0; 16];
+        for (label, key) in labels.chars().zip(KEYPAD.iter()) {
+            scancodes[*key as usize] = layout.scancode(label)?;
+        }
+        Some(Keymap { scancodes })
+    }
+
+    /// `1234` / `QWER` / `ASDF` / `ZXCV` on a QWERTY keyboard.
+    pub fn qwerty() -> Keymap {
+        Keymap::from_labels("1234qwerasdfzxcv", KeyboardLayout::Qwerty).unwrap()
+    }
+
+    /// `1234` / `AZER` / `QSDF` / `WXCV` on an AZERTY keyboard.
+    pub fn azerty() -> Keymap {
+        Keymap::from_labels("1234azerqsdfwxcv", KeyboardLayout::Azerty).unwrap()
+    }
+
+    /// Binds a CHIP-8 key to another scancode.
+    pub fn set(&mut self, key: u8, scancode: u8) {
+        self.scancodes[key as usize & 0xF] = scancode;
+    }
+
+    /// The CHIP-8 key bound to this scancode, if any.
+    pub fn key_for(&self, scancode: u8) -> Option<u8> {
+        self.scancodes.iter().position(|sc| *sc == scancode).map(|key| key as u8)
+    }
+}
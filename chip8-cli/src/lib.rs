@@ -0,0 +1,276 @@
+//! Command-line options of the ProfanOS frontend. They live apart from it
+//! so that they build, and are tested, on the host: the frontend itself only
+//! builds for ProfanOS.
+#![no_std]
+
+extern crate alloc;
+
+pub mod keymap;
+
+use alloc::format;
+use alloc::string::String;
+
+use chip8_core::phosphor::Persistence;
+use chip8_core::platform::Platform;
+use chip8_core::quirks::Quirks;
+
+use crate::keymap::{KeyboardLayout, Keymap};
+
+pub static USAGE: &str = "\
+usage: chip8 ROM [options]
+
+options:
+  --platform NAME     chip8, schip or xochip (guessed from the extension by default)
+  --quirks NAME       vip, chip48, schip or octo (the platform's by default)
+  --ips N             instructions per second (500 by default)
+  --scale N           size of a CHIP-8 pixel on screen (the largest that fits by default)
+  --fg RRGGBB         colour of the lit pixels (FFFFFF by default)
+  --bg RRGGBB         colour of the background (000000 by default)
+  --plane2 RRGGBB     XO-CHIP colour of the pixels lit on the second plane only
+                      (AAAAAA by default)
+  --plane3 RRGGBB     XO-CHIP colour of the pixels lit on both planes (555555 by default)
+  --persistence MODE  off, or or decay[:N], keeps erased pixels on screen against flicker
+  --keymap KEYS       qwerty, azerty, or the 16 keys for 123C 456D 789E A0BF, read
+                      on a QWERTY keyboard unless followed by :azerty
+  --seed N            seed of the random generator (the clock by default)
+  --rng NAME          xorshift (the default, seeded by --seed) or vip:FILE to make
+                      random numbers like the COSMAC VIP, from page 0x01 of the
+                      dump of its interpreter in FILE (512 bytes, or the page)
+  --debug             stop in the debugger on errors instead of quitting
+  --paused            start in the debugger, before the first instruction
+  --trace             print every instruction before it runs
+  --help              print this help
+  --version           print the version
+
+in game: Escape quits, F1-F4 save the state, F5-F8 load it back, F9 and F10
+halve and double the speed and F12 breaks into the debugger, where ips N and
+fps N change the instructions and frames per second.";
+
+/// What to run, and how.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub rom: String,
+    /// None to guess it from the extension of the ROM.
+    pub platform: Option<Platform>,
+    /// None for the defaults of the platform.
+    pub quirks: Option<Quirks>,
+    pub ips: u32,
+    pub scale: Option<usize>,
+    pub fg: Option<u32>,
+    pub bg: Option<u32>,
+    pub plane2: Option<u32>,
+    pub plane3: Option<u32>,
+    pub persistence: Persistence,
+    pub keymap: Keymap,
+    /// None to seed from the clock.
+    pub seed: Option<u32>,
+    /// Dump of the VIP interpreter, to use its random numbers.
+    pub vip_interpreter: Option<String>,
+    /// Errors stop in the debugger instead of quitting.
+    pub debug: bool,
+    /// Starts in the debugger.
+    pub paused: bool,
+    pub trace: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Run(Options),
+    Help,
+    Version,
+}
+
+fn parse_number<T: core::str::FromStr>(option: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", option))?;
+    value.parse().map_err(|_| format!("invalid value for {}: {}", option, value))
+}
+
+/// `RRGGBB`, optionally after `#` or `0x`.
+fn parse_color(option: &str, value: Option<&String>) -> Result<u32, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", option))?;
+    let digits = value.strip_prefix('#').or_else(|| value.strip_prefix("0x")).unwrap_or(value);
+    match u32::from_str_radix(digits, 16) {
+        Ok(color) if digits.len() == 6 => Ok(color),
+        _ => Err(format!("invalid colour for {}: {}", option, value)),
+    }
+}
+
+/// `qwerty`, `azerty`, or 16 key labels with an optional `:qwerty` or `:azerty` layout.
+fn parse_keymap(value: &str) -> Option<Keymap> {
+    let (labels, layout) = match value.split_once(':') {
+        Some((labels, "qwerty")) => (labels, KeyboardLayout::Qwerty),
+        Some((labels, "azerty")) => (labels, KeyboardLayout::Azerty),
+        Some(_) => return None,
+        None => (value, KeyboardLayout::Qwerty),
+    };
+    match labels {
+        "qwerty" => Some(Keymap::qwerty()),
+        "azerty" => Some(Keymap::azerty()),
+        labels => Keymap::from_labels(labels, layout),
+    }
+}
+
+/// Parses the arguments that follow the name of the program.
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let mut options = Options {
+        rom: String::new(),
+        platform: None,
+        quirks: None,
+        ips: 500,
+        scale: None,
+        fg: None,
+        bg: None,
+        plane2: None,
+        plane3: None,
+        persistence: Persistence::Off,
+        keymap: Keymap::qwerty(),
+        seed: None,
+        vip_interpreter: None,
+        debug: false,
+        paused: false,
+        trace: false,
+    };
+    let mut rom = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--version" => return Ok(Command::Version),
+            "--ips" => options.ips = parse_number::<u32>(arg, args.next())?.max(1),
+            "--scale" => options.scale = Some(parse_number::<usize>(arg, args.next())?.max(1)),
+            "--seed" => options.seed = Some(parse_number(arg, args.next())?),
+            "--fg" => options.fg = Some(parse_color(arg, args.next())?),
+            "--bg" => options.bg = Some(parse_color(arg, args.next())?),
+            "--plane2" => options.plane2 = Some(parse_color(arg, args.next())?),
+            "--plane3" => options.plane3 = Some(parse_color(arg, args.next())?),
+            "--debug" => options.debug = true,
+            "--paused" => options.paused = true,
+            "--trace" => options.trace = true,
+            "--platform" => {
+                let name = args.next().ok_or("--platform needs a value")?;
+                options.platform = Some(Platform::from_name(name).ok_or_else(|| format!("unknown platform {}", name))?);
+            }
+            "--quirks" => {
+                let name = args.next().ok_or("--quirks needs a value")?;
+                options.quirks = Some(Quirks::from_name(name).ok_or_else(|| format!("unknown quirks {}", name))?);
+            }
+            "--persistence" => {
+                let name = args.next().ok_or("--persistence needs a value")?;
+                options.persistence =
+                    Persistence::from_name(name).ok_or_else(|| format!("unknown persistence {}", name))?;
+            }
+            "--rng" => {
+                let rng = args.next().ok_or("--rng needs a value")?;
+                options.vip_interpreter = match rng.strip_prefix("vip:") {
+                    Some(path) => Some(String::from(path)),
+                    None if rng == "xorshift" => None,
+                    None => return Err(format!("unknown random generator {}", rng)),
+                };
+            }
+            "--keymap" => {
+                let keys = args.next().ok_or("--keymap needs a value")?;
+                options.keymap = parse_keymap(keys).ok_or_else(|| format!("invalid keymap {}", keys))?;
+            }
+            option if option.starts_with("--") => return Err(format!("unknown option {}", option)),
+            path if rom.is_none() => rom = Some(String::from(path)),
+            extra => return Err(format!("unexpected argument {}", extra)),
+        }
+    }
+    options.rom = rom.ok_or("no ROM given")?;
+    Ok(Command::Run(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec::Vec;
+
+    fn parse_line(line: &str) -> Result<Command, String> {
+        parse(&line.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    fn options(line: &str) -> Options {
+        match parse_line(line) {
+            Ok(Command::Run(options)) => options,
+            other => panic!("{} gave {:?}", line, other),
+        }
+    }
+
+    fn error(line: &str) -> String {
+        parse_line(line).unwrap_err()
+    }
+
+    #[test]
+    fn help_and_version_win_over_the_rest() {
+        assert_eq!(parse_line("--help"), Ok(Command::Help));
+        assert_eq!(parse_line("game.ch8 -h --bogus"), Ok(Command::Help));
+        assert_eq!(parse_line("--version"), Ok(Command::Version));
+    }
+
+    #[test]
+    fn defaults() {
+        let options = options("game.ch8");
+        assert_eq!(options.rom, "game.ch8");
+        assert_eq!((options.platform, options.quirks, options.ips), (None, None, 500));
+        assert_eq!((options.scale, options.fg, options.bg), (None, None, None));
+        assert_eq!(options.keymap, Keymap::qwerty());
+        assert!(!options.debug && !options.paused && !options.trace);
+    }
+
+    #[test]
+    fn options_after_the_rom() {
+        let options = options("game.ch8 --platform schip --quirks chip48 --ips 1000 --seed 7 --paused");
+        assert_eq!(options.platform, Some(Platform::SuperChip));
+        assert_eq!(options.quirks, Some(Quirks::CHIP_48));
+        assert_eq!((options.ips, options.seed, options.paused), (1000, Some(7), true));
+        assert!(!options.debug);
+    }
+
+    #[test]
+    fn wrong_arguments() {
+        assert_eq!(error(""), "no ROM given");
+        assert_eq!(error("--ips 900"), "no ROM given");
+        assert_eq!(error("game.ch8 --fast"), "unknown option --fast");
+        assert_eq!(error("game.ch8 other.ch8"), "unexpected argument other.ch8");
+        assert_eq!(error("game.ch8 --ips"), "--ips needs a value");
+        assert_eq!(error("game.ch8 --ips fast"), "invalid value for --ips: fast");
+        assert_eq!(error("game.ch8 --platform nes"), "unknown platform nes");
+        assert_eq!(error("game.ch8 --rng dice"), "unknown random generator dice");
+        assert_eq!(error("game.ch8 --keymap 123"), "invalid keymap 123");
+    }
+
+    #[test]
+    fn colors() {
+        let options = options("game.ch8 --fg #FF8000 --bg 0x102030 --plane2 abcdef");
+        assert_eq!((options.fg, options.bg, options.plane2), (Some(0xFF8000), Some(0x102030), Some(0xABCDEF)));
+        assert_eq!(error("game.ch8 --fg"), "--fg needs a value");
+        assert_eq!(error("game.ch8 --fg red"), "invalid colour for --fg: red");
+        assert_eq!(error("game.ch8 --bg FFF"), "invalid colour for --bg: FFF");
+        assert_eq!(error("game.ch8 --plane3 #1234567"), "invalid colour for --plane3: #1234567");
+    }
+
+    #[test]
+    fn scale_and_speed_are_at_least_one() {
+        assert_eq!(options("game.ch8 --scale 0").scale, Some(1));
+        assert_eq!(options("game.ch8 --scale 4").scale, Some(4));
+        assert_eq!(options("game.ch8 --ips 0").ips, 1);
+        assert_eq!(error("game.ch8 --scale -1"), "invalid value for --scale: -1");
+    }
+
+    #[test]
+    fn random_generators() {
+        assert_eq!(options("game.ch8").vip_interpreter, None);
+        assert_eq!(options("game.ch8 --rng vip:vip.bin --rng xorshift").vip_interpreter, None);
+        assert_eq!(options("game.ch8 --rng vip:vip.bin").vip_interpreter.as_deref(), Some("vip.bin"));
+    }
+
+    #[test]
+    fn keymaps() {
+        assert_eq!(options("game.ch8 --keymap azerty").keymap, Keymap::azerty());
+        assert_eq!(options("game.ch8 --keymap 1234azerqsdfwxcv:azerty").keymap, Keymap::azerty());
+        assert_eq!(options("game.ch8 --keymap 1234qwerasdfzxcv").keymap, Keymap::qwerty());
+        assert_eq!(error("game.ch8 --keymap 1234azerqsdfwxc;"), "invalid keymap 1234azerqsdfwxc;");
+        assert_eq!(error("game.ch8 --keymap qwerty:dvorak"), "invalid keymap qwerty:dvorak");
+    }
+}
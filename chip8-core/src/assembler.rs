@@ -0,0 +1,662 @@
+use core::fmt;
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::disasm::ROM_START;
+
+use crate::insts::Instruction;
+
+/// Macros can call other macros, this stops a macro that expands to itself.
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// Why a source file could not be assembled, with the position of the culprit (1-based).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+/// Splits the source on whitespace, `#` starts a comment up to the end of the line.
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (n, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        let mut start = None;
+        for (column, c) in code.char_indices().chain(core::iter::once((code.len(), ' '))) {
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(column),
+                (true, Some(begin)) => {
+                    tokens.push(Token {
+                        text: code[begin..column].to_string(),
+                        line: n + 1,
+                        column: begin + 1,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    tokens
+}
+
+/// Which part of the output a label fills once it is known.
+#[derive(Debug, Clone, Copy)]
+enum FixupKind {
+    /// The NNN of the opcode.
+    Addr12,
+    /// The word following `F000`.
+    Long16,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// An address operand, labels may be defined further down.
+enum Address {
+    Known(u16),
+    Forward(Token),
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    pos: usize,
+    output: Vec<u8>,
+    labels: BTreeMap<String, u16>,
+    consts: BTreeMap<String, i64>,
+    aliases: BTreeMap<String, u8>,
+    macros: BTreeMap<String, Macro>,
+    fixups: Vec<(usize, Token, FixupKind)>,
+    loops: Vec<u16>,
+    expansions: usize,
+}
+
+/// Assembles Octo source into a ROM to load at 0x200 with `VM::setmemory`.
+///
+/// Supported: labels (`: name`), `:const`, `:alias`, `:macro`, `:call`,
+/// `:byte`, `:org`, every instruction of CHIP-8, SUPER-CHIP and XO-CHIP,
+/// `if ... then`, `loop`/`again` and raw bytes. Calling a subroutine is
+/// writing its name.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler {
+        tokens: tokenize(source),
+        pos: 0,
+        output: Vec::new(),
+        labels: BTreeMap::new(),
+        consts: BTreeMap::new(),
+        aliases: BTreeMap::new(),
+        macros: BTreeMap::new(),
+        fixups: Vec::new(),
+        loops: Vec::new(),
+        expansions: 0,
+    };
+    while assembler.pos < assembler.tokens.len() {
+        assembler.statement()?;
+    }
+    assembler.finish()
+}
+
+fn parse_literal(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v' | 'V'), Some(digit), None) => digit.to_digit(16).map(|n| n as u8),
+        _ => None,
+    }
+}
+
+impl Assembler {
+    /// Address of the next byte, an error at `token` past the 64 KiB of XO-CHIP.
+    fn here(&self, token: &Token) -> Result<u16, AsmError> {
+        u16::try_from(self.output.len())
+            .ok()
+            .and_then(|len| ROM_START.checked_add(len))
+            .ok_or_else(|| token.error("the program goes past 0xFFFF"))
+    }
+
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => {
+                let last = self.tokens.last().map(|t| (t.line, t.column + t.text.len())).unwrap_or((1, 1));
+                Err(AsmError {
+                    line: last.0,
+                    column: last.1,
+                    message: "unexpected end of file".to_string(),
+                })
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(token.error(format!("expected '{}', found '{}'", text, token.text)));
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.output.extend_from_slice(&instruction.encode().to_be_bytes());
+    }
+
+    fn emit_addr(&mut self, instruction: impl Fn(u16) -> Instruction, addr: Address) {
+        match addr {
+            Address::Known(addr) => self.emit(instruction(addr)),
+            Address::Forward(token) => {
+                self.fixups.push((self.output.len(), token, FixupKind::Addr12));
+                self.emit(instruction(0));
+            }
+        }
+    }
+
+    fn register_of(&self, token: &Token) -> Option<u8> {
+        parse_register(&token.text).or_else(|| self.aliases.get(&token.text).copied())
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        self.register_of(&token)
+            .ok_or_else(|| token.error(format!("expected a register, found '{}'", token.text)))
+    }
+
+    /// A number, a constant or an already defined label.
+    fn value_of(&self, token: &Token) -> Option<i64> {
+        parse_literal(&token.text)
+            .or_else(|| self.consts.get(&token.text).copied())
+            .or_else(|| self.labels.get(&token.text).map(|addr| *addr as i64))
+    }
+
+    fn value(&mut self, min: i64, max: i64) -> Result<i64, AsmError> {
+        let token = self.next()?;
+        let value = self
+            .value_of(&token)
+            .ok_or_else(|| token.error(format!("expected a number, found '{}'", token.text)))?;
+        if value < min || value > max {
+            return Err(token.error(format!("{} does not fit in [{}, {}]", value, min, max)));
+        }
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        Ok((self.value(-128, 255)? & 0xFF) as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        Ok(self.value(0, 15)? as u8)
+    }
+
+    fn is_identifier(text: &str) -> bool {
+        parse_literal(text).is_none()
+            && parse_register(text).is_none()
+            && !text.starts_with(':')
+            && text != "{"
+            && text != "}"
+    }
+
+    fn address(&mut self, max: i64) -> Result<Address, AsmError> {
+        let token = self.next()?;
+        if let Some(value) = self.value_of(&token) {
+            if value < 0 || value > max {
+                return Err(token.error(format!("address {:#X} is out of range", value)));
+            }
+            return Ok(Address::Known(value as u16));
+        }
+        if !Assembler::is_identifier(&token.text) {
+            return Err(token.error(format!("expected an address, found '{}'", token.text)));
+        }
+        Ok(Address::Forward(token))
+    }
+
+    fn name(&mut self) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        if !Assembler::is_identifier(&token.text) {
+            return Err(token.error(format!("'{}' can't be used as a name", token.text)));
+        }
+        Ok(token)
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                let here = self.here(&name)?;
+                if self.labels.insert(name.text.clone(), here).is_some() {
+                    return Err(name.error(format!("label '{}' is defined twice", name.text)));
+                }
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value(i64::MIN, i64::MAX)?;
+                self.consts.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name.text, register);
+            }
+            ":macro" => self.define_macro()?,
+            ":call" => {
+                let addr = self.address(0xFFF)?;
+                self.emit_addr(Instruction::Call, addr);
+            }
+            ":byte" => {
+                let byte = self.byte()?;
+                self.output.push(byte);
+            }
+            ":org" => {
+                let target = self.value(ROM_START as i64, 0xFFFF)? as u16;
+                if target < self.here(&token)? {
+                    return Err(token.error(format!(":org {:#X} is behind the current address", target)));
+                }
+                self.output.resize((target - ROM_START) as usize, 0);
+            }
+            "clear" => self.emit(Instruction::ClearScreen),
+            "audio" => self.emit(Instruction::LoadAudio),
+            "return" => self.emit(Instruction::Ret),
+            "exit" => self.emit(Instruction::Exit),
+            "lores" => self.emit(Instruction::LowRes),
+            "hires" => self.emit(Instruction::HighRes),
+            "scroll-left" => self.emit(Instruction::ScrollLeft),
+            "scroll-right" => self.emit(Instruction::ScrollRight),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollDown(n));
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollUp(n));
+            }
+            "jump" => {
+                let addr = self.address(0xFFF)?;
+                self.emit_addr(Instruction::Jump, addr);
+            }
+            "jump0" => {
+                let addr = self.address(0xFFF)?;
+                self.emit_addr(Instruction::Jump2, addr);
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreBCD(x));
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreFlags(x));
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::ReadFlags(x));
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let save = token.text == "save";
+                if self.peek() == Some("-") {
+                    self.pos += 1;
+                    let y = self.register()?;
+                    self.emit(if save { Instruction::SaveRange(x, y) } else { Instruction::LoadRange(x, y) });
+                } else {
+                    self.emit(if save { Instruction::StoreRegisters(x) } else { Instruction::ReadRegisters(x) });
+                }
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Instruction::Draw(x, y, n));
+            }
+            "plane" => {
+                let n = self.value(0, 3)? as u8;
+                self.emit(Instruction::SelectPlane(n));
+            }
+            "i" => self.index_statement()?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match token.text.as_str() {
+                    "delay" => Instruction::SetDelay(x),
+                    "buzzer" => Instruction::SetSound(x),
+                    _ => Instruction::SetPitch(x),
+                });
+            }
+            "if" => self.condition()?,
+            "loop" => {
+                let here = self.here(&token)?;
+                self.loops.push(here);
+            }
+            "again" => {
+                let start = self.loops.pop().ok_or_else(|| token.error("'again' without 'loop'"))?;
+                self.emit(Instruction::Jump(start));
+            }
+            _ => {
+                if let Some(x) = self.register_of(&token) {
+                    return self.register_statement(x);
+                }
+                if let Some(value) = parse_literal(&token.text).or_else(|| self.consts.get(&token.text).copied()) {
+                    if !(-128..=255).contains(&value) {
+                        return Err(token.error(format!("{} does not fit in a byte", value)));
+                    }
+                    self.output.push((value & 0xFF) as u8);
+                    return Ok(());
+                }
+                if self.macros.contains_key(&token.text) {
+                    return self.expand_macro(&token);
+                }
+                if !Assembler::is_identifier(&token.text) {
+                    return Err(token.error(format!("unexpected '{}'", token.text)));
+                }
+                // anything else is the name of a subroutine to call
+                self.pos -= 1;
+                let addr = self.address(0xFFF)?;
+                self.emit_addr(Instruction::Call, addr);
+            }
+        }
+        Ok(())
+    }
+
+    fn index_statement(&mut self) -> Result<(), AsmError> {
+        let op = self.next()?;
+        match op.text.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.pos += 1;
+                    let x = self.register()?;
+                    self.emit(Instruction::SpriteDigit(x));
+                }
+                Some("bighex") => {
+                    self.pos += 1;
+                    let x = self.register()?;
+                    self.emit(Instruction::BigSpriteDigit(x));
+                }
+                Some("long") => {
+                    self.pos += 1;
+                    let addr = self.address(0xFFFF)?;
+                    self.emit(Instruction::LongI);
+                    match addr {
+                        Address::Known(addr) => self.output.extend_from_slice(&addr.to_be_bytes()),
+                        Address::Forward(token) => {
+                            self.fixups.push((self.output.len() - 2, token, FixupKind::Long16));
+                            self.output.extend_from_slice(&[0, 0]);
+                        }
+                    }
+                }
+                _ => {
+                    let addr = self.address(0xFFF)?;
+                    self.emit_addr(Instruction::SetI, addr);
+                }
+            },
+            "+=" => {
+                let x = self.register()?;
+                self.emit(Instruction::AddI(x));
+            }
+            _ => return Err(op.error(format!("unknown operator 'i {}'", op.text))),
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), AsmError> {
+        let op = self.next()?;
+        let rhs = self.next()?;
+        let y = self.register_of(&rhs);
+        let instruction = match (op.text.as_str(), y) {
+            (":=", Some(y)) => Instruction::STORE(x, y),
+            (":=", None) => match rhs.text.as_str() {
+                "delay" => Instruction::ReadDelay(x),
+                "key" => Instruction::WaitKey(x),
+                "random" => Instruction::Random(x, self.byte()?),
+                _ => {
+                    self.pos -= 1;
+                    Instruction::SetRegister(x, self.byte()?)
+                }
+            },
+            ("+=", Some(y)) => Instruction::ADD(x, y),
+            ("+=", None) => {
+                self.pos -= 1;
+                Instruction::AddRegister(x, self.byte()?)
+            }
+            ("-=", Some(y)) => Instruction::SUB(x, y),
+            ("-=", None) => {
+                self.pos -= 1;
+                Instruction::AddRegister(x, self.byte()?.wrapping_neg())
+            }
+            ("=-", Some(y)) => Instruction::SUBN(x, y),
+            ("|=", Some(y)) => Instruction::OR(x, y),
+            ("&=", Some(y)) => Instruction::AND(x, y),
+            ("^=", Some(y)) => Instruction::XOR(x, y),
+            (">>=", Some(y)) => Instruction::SHR(x, y),
+            ("<<=", Some(y)) => Instruction::SHL(x, y),
+            _ => return Err(op.error(format!("unknown operation 'v{:X} {} {}'", x, op.text, rhs.text))),
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    /// `if COND then`: skips the next statement unless COND holds.
+    fn condition(&mut self) -> Result<(), AsmError> {
+        let x = self.register()?;
+        let op = self.next()?;
+        let instruction = match op.text.as_str() {
+            "key" => Instruction::SkipIfNotPressed(x),
+            "-key" => Instruction::SkipIfPressed(x),
+            "==" | "!=" => {
+                let rhs = self.next()?;
+                let equal = op.text == "==";
+                match self.register_of(&rhs) {
+                    Some(y) if equal => Instruction::NR2SkipNextInstruction(x, y),
+                    Some(y) => Instruction::R2SkipNextInstruction(x, y),
+                    None => {
+                        self.pos -= 1;
+                        let value = self.byte()?;
+                        if equal {
+                            Instruction::NSkipNextInstruction(x, value)
+                        } else {
+                            Instruction::SkipNextInstruction(x, value)
+                        }
+                    }
+                }
+            }
+            _ => return Err(op.error(format!("unknown condition '{}'", op.text))),
+        };
+        self.expect("then")?;
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.name()?;
+        let mut params = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            params.push(token.text);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            body.push(token);
+        }
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    /// Replaces the call of a macro and its arguments by the body of the macro.
+    fn expand_macro(&mut self, call: &Token) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(call.error("too many macro expansions, is a macro calling itself?"));
+        }
+        let count = self.macros[&call.text].params.len();
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            args.push(self.next()?);
+        }
+        let macro_ = &self.macros[&call.text];
+        let body: Vec<Token> = macro_
+            .body
+            .iter()
+            .map(|token| match macro_.params.iter().position(|param| *param == token.text) {
+                Some(n) => Token {
+                    text: args[n].text.clone(),
+                    ..token.clone()
+                },
+                None => token.clone(),
+            })
+            .collect();
+        self.tokens.splice(self.pos..self.pos, body);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, AsmError> {
+        let last = self.tokens.last().map(|t| (t.line, t.column)).unwrap_or((1, 1));
+        if !self.loops.is_empty() {
+            return Err(AsmError {
+                line: last.0,
+                column: last.1,
+                message: "'loop' without 'again'".to_string(),
+            });
+        }
+        if self.output.len() > 0x10000 - ROM_START as usize {
+            return Err(AsmError {
+                line: last.0,
+                column: last.1,
+                message: "the program goes past 0xFFFF".to_string(),
+            });
+        }
+        for (offset, token, kind) in core::mem::take(&mut self.fixups) {
+            let addr = *self
+                .labels
+                .get(&token.text)
+                .ok_or_else(|| token.error(format!("undefined label '{}'", token.text)))?;
+            match kind {
+                FixupKind::Addr12 => {
+                    if addr > 0xFFF {
+                        return Err(token.error(format!("label '{}' is above 0xFFF, use 'i := long'", token.text)));
+                    }
+                    self.output[offset] |= (addr >> 8) as u8;
+                    self.output[offset + 1] = addr as u8;
+                }
+                FixupKind::Long16 => {
+                    self.output[offset + 2..offset + 4].copy_from_slice(&addr.to_be_bytes());
+                }
+            }
+        }
+        Ok(self.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> (usize, usize, String) {
+        let error = assemble(source).unwrap_err();
+        (error.line, error.column, error.message)
+    }
+
+    #[test]
+    fn errors_point_at_the_token() {
+        assert_eq!(error("clear\n  v0 := 300"), (2, 9, "300 does not fit in [-128, 255]".to_string()));
+        assert_eq!(error("  jump nowhere"), (1, 8, "undefined label 'nowhere'".to_string()));
+        assert_eq!(error(": a\n: a"), (2, 3, "label 'a' is defined twice".to_string()));
+        assert_eq!(error("sprite v0"), (1, 10, "unexpected end of file".to_string()));
+    }
+
+    #[test]
+    fn forward_references_are_fixed_up() {
+        assert_eq!(
+            assemble("jump end  i := data  i := long data  : end  : data 0xAB").unwrap(),
+            [0x12, 0x08, 0xA2, 0x08, 0xF0, 0x00, 0x02, 0x08, 0xAB]
+        );
+        assert_eq!(assemble("sub  : sub  return").unwrap(), [0x22, 0x02, 0x00, 0xEE]);
+    }
+
+    #[test]
+    fn loops_jump_back() {
+        assert_eq!(assemble("clear  loop  v0 += 1  again").unwrap(), [0x00, 0xE0, 0x70, 0x01, 0x12, 0x02]);
+        assert_eq!(error("loop  clear").2, "'loop' without 'again'");
+        assert_eq!(error("again"), (1, 1, "'again' without 'loop'".to_string()));
+    }
+
+    #[test]
+    fn macros_and_aliases() {
+        let source = "
+            :alias x v3
+            :macro add-to reg value { reg += value }
+            add-to x 2
+            :const five 5
+            add-to v1 five
+        ";
+        assert_eq!(assemble(source).unwrap(), [0x73, 0x02, 0x71, 0x05]);
+        assert_eq!(error(":macro me { me }  me").2, "too many macro expansions, is a macro calling itself?");
+    }
+
+    #[test]
+    fn the_program_stops_at_0xffff() {
+        assert_eq!(assemble(":org 0xFFFE  0xAB 0xCD").unwrap().len(), 0x10000 - 0x200);
+        assert_eq!(error(":org 0xFFFF  0xAB 0xCD  : end"), (1, 27, "the program goes past 0xFFFF".to_string()));
+        assert_eq!(error(":org 0xFFFF  0xAB 0xCD").2, "the program goes past 0xFFFF");
+    }
+}
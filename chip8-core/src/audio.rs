@@ -0,0 +1,140 @@
+//! Sound synthesis, for frontends that produce samples rather than drive a buzzer.
+//!
+//! XO-CHIP plays a 128-bit pattern (loaded by `F002`) in a loop, at a rate set
+//! by the pitch register (`FX3A`). The other platforms only have a beep, played
+//! here as a square wave at the frequency of the [`Tone`].
+
+use core::f32::consts::LN_2;
+
+use crate::vm::Tone;
+
+/// Pitch register at reset, the pattern then plays at 4000 bits per second.
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// Bits in an XO-CHIP audio pattern.
+const PATTERN_BITS: f32 = 128.0;
+
+/// What the buzzer plays during one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Audio {
+    /// The sound timer was running.
+    pub playing: bool,
+    pub tone: Tone,
+    /// The pattern loaded by `F002`, None until the ROM loads one.
+    pub pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+}
+
+/// Rate in bits per second at which XO-CHIP plays the pattern:
+/// 4000 * 2 ^ ((pitch - 64) / 48).
+pub fn playback_rate(pitch: u8) -> f32 {
+    4000.0 * exp2((pitch as f32 - 64.0) / 48.0)
+}
+
+/// 2 ^ x, `powf` isn't available without std. Exact on integers, the
+/// fractional part uses a short series on fraction / 16, squared 4 times.
+fn exp2(x: f32) -> f32 {
+    let mut whole = x as i32;
+    if (whole as f32) > x {
+        whole -= 1; // floor of negative numbers
+    }
+    let t = (x - whole as f32) * LN_2 / 16.0;
+    let mut power = 1.0 + t * (1.0 + t / 2.0 * (1.0 + t / 3.0 * (1.0 + t / 4.0)));
+    for _ in 0..4 {
+        power *= power;
+    }
+    for _ in 0..whole.unsigned_abs() {
+        power = if whole < 0 { power / 2.0 } else { power * 2.0 };
+    }
+    power
+}
+
+/// Turns what the VM plays into signed 16-bit mono samples.
+pub struct Synth {
+    sample_rate: u32,
+    /// Position in the pattern in bits, or in the square wave in periods.
+    phase: f32,
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32) -> Self {
+        Synth { sample_rate: sample_rate.max(1), phase: 0.0 }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Fills `out` with what `audio` sounds like, continuing the wave where
+    /// the previous call stopped so frames join without clicks.
+    pub fn render(&mut self, audio: &Audio, out: &mut [i16]) {
+        if !audio.playing || audio.tone.volume == 0 {
+            out.fill(0);
+            self.phase = 0.0;
+            return;
+        }
+        let amplitude = i16::MAX as f32 * audio.tone.volume.min(100) as f32 / 100.0;
+        let (step, length) = match audio.pattern {
+            Some(_) => (playback_rate(audio.pitch) / self.sample_rate as f32, PATTERN_BITS),
+            None => (audio.tone.frequency as f32 / self.sample_rate as f32, 1.0),
+        };
+        for sample in out.iter_mut() {
+            let high = match &audio.pattern {
+                Some(pattern) => {
+                    let bit = self.phase as usize;
+                    pattern[bit / 8] & (0x80 >> (bit % 8)) != 0
+                }
+                None => self.phase < 0.5,
+            };
+            *sample = if high { amplitude as i16 } else { -amplitude as i16 };
+            self.phase += step;
+            while self.phase >= length {
+                self.phase -= length;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(actual: f32, expected: f32) -> bool {
+        (actual - expected).abs() <= expected * 0.0001
+    }
+
+    #[test]
+    fn playback_rate_doubles_every_48_steps() {
+        assert_eq!(playback_rate(64), 4000.0);
+        assert_eq!(playback_rate(112), 8000.0);
+        assert_eq!(playback_rate(16), 2000.0);
+        assert!(close(playback_rate(88), 5656.854));
+        assert!(close(playback_rate(0), 1587.401));
+        assert!(close(playback_rate(255), 63_082.44));
+    }
+
+    #[test]
+    fn the_pattern_plays_at_the_pitch() {
+        let mut pattern = [0; 16];
+        pattern[0] = 0xF0;
+        let audio = Audio { playing: true, tone: Tone { frequency: 440, volume: 100 }, pattern: Some(pattern), pitch: 64 };
+        // 2 samples per bit
+        let mut out = [0; 24];
+        Synth::new(8000).render(&audio, &mut out);
+        let high = out.iter().take_while(|sample| **sample == i16::MAX).count();
+        assert_eq!(high, 8);
+        assert!(out[8..].iter().all(|sample| *sample == -i16::MAX));
+    }
+
+    #[test]
+    fn beeps_are_square_waves() {
+        let audio = Audio { playing: true, tone: Tone { frequency: 1000, volume: 50 }, pattern: None, pitch: DEFAULT_PITCH };
+        let mut synth = Synth::new(8000);
+        let mut out = [0; 16];
+        synth.render(&audio, &mut out);
+        assert_eq!(out[..8], [16383, 16383, 16383, 16383, -16383, -16383, -16383, -16383]);
+        assert_eq!(out[..8], out[8..]);
+        synth.render(&Audio { playing: false, ..audio }, &mut out);
+        assert!(out.iter().all(|sample| *sample == 0));
+    }
+}
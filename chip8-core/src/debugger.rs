@@ -0,0 +1,426 @@
+use core::fmt::{self, Write};
+
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+
+use crate::error::VmError;
+
+use crate::insts::Instruction;
+
+use crate::disasm::{self, Syntax};
+
+use crate::vm::{DisplayHandler, KeyboardHandler, RandomHandler, SoundHandler, StepOutcome, VM};
+
+/// Upper bound of instructions for `next` and `finish`, so a ROM stuck in a
+/// loop doesn't freeze the debugger.
+pub const MAX_STEPS: usize = 1_000_000;
+
+/// Why the debugger gave control back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested step(s) are done.
+    Done,
+    /// The program counter reached a breakpoint.
+    Breakpoint(u16),
+    /// The VM waits for a key, the vertical blank or exited.
+    Outcome(StepOutcome),
+    /// The VM failed, it is left on the faulting instruction.
+    Error(VmError),
+    /// [`MAX_STEPS`] instructions ran without reaching the target.
+    Limit,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Done => write!(f, "done"),
+            StopReason::Breakpoint(addr) => write!(f, "breakpoint at {:03X}", addr),
+            StopReason::Outcome(outcome) => write!(f, "stopped: {:?}", outcome),
+            StopReason::Error(error) => write!(f, "error: {}", error),
+            StopReason::Limit => write!(f, "stopped after {} instructions", MAX_STEPS),
+        }
+    }
+}
+
+/// What the frontend should do after a REPL command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplAction {
+    /// Read the next command.
+    Prompt,
+    /// Run the ROM again until a breakpoint is hit.
+    Resume,
+    /// Stop the emulator.
+    Quit,
+}
+
+static HELP: &str = "\
+s, step [n]      execute n instructions (1 by default)
+n, next          step over a call
+f, finish        run until the current subroutine returns
+c, continue      resume execution until a breakpoint
+b, break ADDR    add a breakpoint
+d, delete ADDR   remove a breakpoint
+bl               list breakpoints
+r, regs          show registers, stack and timers
+x ADDR [LEN]     dump memory
+l, list [ADDR]   disassemble around ADDR (the PC by default)
+q, quit          stop the emulator";
+
+/// Breakpoints and stepping on top of a [`VM`], through its public inspection API only.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    /// The timers are decreased once every `steps_per_tick` instructions while stepping.
+    pub steps_per_tick: usize,
+    steps: usize,
+}
+
+impl Debugger {
+    pub fn new(steps_per_tick: usize) -> Self {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            steps_per_tick: steps_per_tick.max(1),
+            steps: 0,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Returns false if there was no breakpoint there.
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn is_breakpoint(&self, addr: u16) -> bool {
+        self.breakpoints.contains(&addr)
+    }
+
+    /// Executes one instruction.
+    pub fn step<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(
+        &mut self,
+        vm: &mut VM<T, T2, T3, T4>,
+    ) -> Result<StepOutcome, VmError> {
+        let outcome = vm.process();
+        self.steps += 1;
+        if self.steps.is_multiple_of(self.steps_per_tick) {
+            vm.decrease_timer();
+        }
+        outcome
+    }
+
+    /// Steps until `done` says so, a breakpoint is reached or the VM stops.
+    fn run_until<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(
+        &mut self,
+        vm: &mut VM<T, T2, T3, T4>,
+        mut done: impl FnMut(&VM<T, T2, T3, T4>) -> bool,
+    ) -> StopReason {
+        for _ in 0..MAX_STEPS {
+            match self.step(vm) {
+                Ok(StepOutcome::Continue) => {}
+                // waiting for the timers: keep going, they are decreased while stepping
+                Ok(StepOutcome::WaitingForVblank) => {}
+                Ok(outcome) => return StopReason::Outcome(outcome),
+                Err(error) => return StopReason::Error(error),
+            }
+            if done(vm) {
+                return StopReason::Done;
+            }
+            if self.is_breakpoint(vm.pc()) {
+                return StopReason::Breakpoint(vm.pc());
+            }
+        }
+        StopReason::Limit
+    }
+
+    /// Executes `count` instructions, stopping early on breakpoints.
+    pub fn step_n<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(
+        &mut self,
+        vm: &mut VM<T, T2, T3, T4>,
+        count: usize,
+    ) -> StopReason {
+        let mut left = count.max(1);
+        self.run_until(vm, |_| {
+            left -= 1;
+            left == 0
+        })
+    }
+
+    /// Runs until a breakpoint is reached or the VM stops, for frontends that
+    /// resume without their own loop.
+    pub fn run<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(
+        &mut self,
+        vm: &mut VM<T, T2, T3, T4>,
+    ) -> StopReason {
+        self.run_until(vm, |_| false)
+    }
+
+    /// Executes one instruction, or a whole subroutine if it is a `Call`.
+    pub fn step_over<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(
+        &mut self,
+        vm: &mut VM<T, T2, T3, T4>,
+    ) -> StopReason {
+        let pc = vm.pc();
+        let is_call = matches!(vm.opcode_at(pc as usize).map(Instruction::new), Some(Instruction::Call(_)));
+        if !is_call {
+            return self.step_n(vm, 1);
+        }
+        let depth = vm.stack().len();
+        let after = pc + vm.instruction_len(pc as usize) as u16;
+        self.run_until(vm, |vm| vm.stack().len() == depth && vm.pc() == after)
+    }
+
+    /// Runs until the current subroutine returns to its caller.
+    pub fn finish<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(
+        &mut self,
+        vm: &mut VM<T, T2, T3, T4>,
+    ) -> StopReason {
+        let depth = vm.stack().len();
+        if depth == 0 {
+            return StopReason::Done;
+        }
+        self.run_until(vm, |vm| vm.stack().len() < depth)
+    }
+
+    /// V0-VF, I, the PC, the stack and the timers.
+    pub fn dump_registers<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(vm: &VM<T, T2, T3, T4>) -> String {
+        let mut out = String::new();
+        for (n, value) in vm.registers().iter().enumerate() {
+            let sep = if n % 8 == 7 { "\n" } else { "  " };
+            let _ = write!(out, "V{:X}={:02X}{}", n, value, sep);
+        }
+        let _ = writeln!(out, "PC={:03X}  I={:03X}  DT={:02X}  ST={:02X}", vm.pc(), vm.index(), vm.delay_timer(), vm.sound_timer());
+        let _ = write!(out, "stack:");
+        for addr in vm.stack() {
+            let _ = write!(out, " {:03X}", addr);
+        }
+        out
+    }
+
+    /// Hexadecimal dump of `len` bytes from `addr`, 16 per line.
+    pub fn dump_memory<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(vm: &VM<T, T2, T3, T4>, addr: usize, len: usize) -> String {
+        let mut out = String::new();
+        let end = addr.saturating_add(len).min(vm.memory.len());
+        for (n, line) in vm.memory[addr.min(end)..end].chunks(16).enumerate() {
+            let _ = write!(out, "{:04X}:", addr + n * 16);
+            for byte in line {
+                let _ = write!(out, " {:02X}", byte);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Decodes the instructions from `before` instructions ahead of `addr` to `after` past it.
+    pub fn disassemble<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(
+        &self,
+        vm: &VM<T, T2, T3, T4>,
+        addr: u16,
+        before: usize,
+        after: usize,
+    ) -> String {
+        let mut out = String::new();
+        let start = (addr as usize).saturating_sub(2 * before);
+        let mut current = start;
+        while current <= addr as usize + 2 * after {
+            let Some(opcode) = vm.opcode_at(current) else {
+                break;
+            };
+            let marker = if current == vm.pc() as usize { '>' } else { ' ' };
+            let bp = if self.is_breakpoint(current as u16) { '*' } else { ' ' };
+            let instruction = Instruction::new(opcode);
+            let long = vm.opcode_at(current + 2).unwrap_or(0);
+            let text = disasm::format_instruction(&instruction, long, Syntax::Octo, |_| None);
+            let _ = writeln!(out, "{}{}{:03X}: {:04X}  {}", marker, bp, current, opcode, text);
+            current += vm.instruction_len(current);
+        }
+        out
+    }
+
+    /// Runs one REPL command and appends what it prints to `out`.
+    pub fn execute<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(
+        &mut self,
+        vm: &mut VM<T, T2, T3, T4>,
+        line: &str,
+        out: &mut String,
+    ) -> ReplAction {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let arg = words.next().map(parse_number);
+        let arg2 = words.next().map(parse_number);
+
+        let stop = match (command, arg) {
+            ("", _) => return ReplAction::Prompt,
+            ("s" | "step", None) => self.step_n(vm, 1),
+            ("s" | "step", Some(Some(count))) => self.step_n(vm, count.max(1)),
+            ("n" | "next", _) => self.step_over(vm),
+            ("f" | "finish", _) => self.finish(vm),
+            ("c" | "continue", _) => {
+                // leave the breakpoint we are sitting on before resuming
+                if self.is_breakpoint(vm.pc()) {
+                    let stop = self.step_n(vm, 1);
+                    if stop != StopReason::Done {
+                        report(stop, out);
+                        return ReplAction::Prompt;
+                    }
+                }
+                return ReplAction::Resume;
+            }
+            ("b" | "break" | "d" | "delete" | "l" | "list", Some(Some(addr))) if address(vm, addr).is_none() => {
+                let _ = writeln!(out, "no address {:X} in memory", addr);
+                return ReplAction::Prompt;
+            }
+            ("b" | "break", Some(Some(addr))) => {
+                self.add_breakpoint(addr as u16);
+                let _ = writeln!(out, "breakpoint at {:03X}", addr);
+                return ReplAction::Prompt;
+            }
+            ("d" | "delete", Some(Some(addr))) => {
+                if !self.remove_breakpoint(addr as u16) {
+                    let _ = writeln!(out, "no breakpoint at {:03X}", addr);
+                }
+                return ReplAction::Prompt;
+            }
+            ("bl", _) => {
+                for addr in self.breakpoints() {
+                    let _ = writeln!(out, "{:03X}", addr);
+                }
+                return ReplAction::Prompt;
+            }
+            ("r" | "regs", _) => {
+                let _ = writeln!(out, "{}", Debugger::dump_registers(vm));
+                return ReplAction::Prompt;
+            }
+            ("x", Some(Some(addr))) => {
+                let len = arg2.flatten().unwrap_or(16);
+                out.push_str(&Debugger::dump_memory(vm, addr, len));
+                return ReplAction::Prompt;
+            }
+            ("l" | "list", None) => {
+                out.push_str(&self.disassemble(vm, vm.pc(), 4, 8));
+                return ReplAction::Prompt;
+            }
+            ("l" | "list", Some(Some(addr))) => {
+                out.push_str(&self.disassemble(vm, addr as u16, 4, 8));
+                return ReplAction::Prompt;
+            }
+            ("q" | "quit", _) => return ReplAction::Quit,
+            ("h" | "help", _) => {
+                let _ = writeln!(out, "{}", HELP);
+                return ReplAction::Prompt;
+            }
+            _ => {
+                let _ = writeln!(out, "unknown command, type help");
+                return ReplAction::Prompt;
+            }
+        };
+        report(stop, out);
+        out.push_str(&self.disassemble(vm, vm.pc(), 0, 0));
+        ReplAction::Prompt
+    }
+}
+
+/// `addr` as a u16, if it is in the memory of the VM.
+fn address<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(vm: &VM<T, T2, T3, T4>, addr: usize) -> Option<u16> {
+    u16::try_from(addr).ok().filter(|addr| (*addr as usize) < vm.memory.len())
+}
+
+/// Hexadecimal by default, `#` for decimal: `2A0`, `0x2A0` and `#672` are the same address.
+fn parse_number(word: &str) -> Option<usize> {
+    if let Some(decimal) = word.strip_prefix('#') {
+        return decimal.parse().ok();
+    }
+    let hex = word.strip_prefix("0x").unwrap_or(word);
+    usize::from_str_radix(hex, 16).ok()
+}
+
+fn report(stop: StopReason, out: &mut String) {
+    if stop != StopReason::Done {
+        let _ = writeln!(out, "{}", stop);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::platform::Platform;
+    use crate::quirks::Quirks;
+    use crate::vm::tests::{test_vm, TestVm};
+
+    // 200: call 206, 202: v1 := 1, 204: jump 204, 206: v0 := 7, 208: return
+    const CALL: [u8; 10] = [0x22, 0x06, 0x61, 0x01, 0x12, 0x04, 0x60, 0x07, 0x00, 0xEE];
+
+    fn vm() -> TestVm {
+        test_vm(&CALL, Platform::Chip8, Quirks::COSMAC_VIP)
+    }
+
+    #[test]
+    fn next_steps_over_calls() {
+        let mut vm = vm();
+        let mut debugger = Debugger::new(10);
+        assert_eq!(debugger.step_over(&mut vm), StopReason::Done);
+        assert_eq!((vm.pc(), vm.registers()[0]), (0x202, 7));
+        assert_eq!(debugger.step_over(&mut vm), StopReason::Done);
+        assert_eq!((vm.pc(), vm.registers()[1]), (0x204, 1));
+    }
+
+    #[test]
+    fn finish_returns_to_the_caller() {
+        let mut vm = vm();
+        let mut debugger = Debugger::new(10);
+        debugger.step_n(&mut vm, 1);
+        assert_eq!((vm.pc(), vm.stack().len()), (0x206, 1));
+        assert_eq!(debugger.finish(&mut vm), StopReason::Done);
+        assert_eq!((vm.pc(), vm.stack().len()), (0x202, 0));
+        assert_eq!(debugger.finish(&mut vm), StopReason::Done);
+        assert_eq!(vm.pc(), 0x202);
+    }
+
+    #[test]
+    fn breakpoints_stop_the_run() {
+        let mut vm = vm();
+        let mut debugger = Debugger::new(10);
+        debugger.add_breakpoint(0x208);
+        assert_eq!(debugger.step_over(&mut vm), StopReason::Breakpoint(0x208));
+        assert_eq!(debugger.step_n(&mut vm, 5), StopReason::Done);
+        assert_eq!(vm.pc(), 0x204);
+        assert_eq!(debugger.run(&mut vm), StopReason::Limit);
+
+        let mut vm = self::vm();
+        let mut out = String::new();
+        assert_eq!(debugger.execute(&mut vm, "c", &mut out), ReplAction::Resume);
+        assert_eq!(debugger.run(&mut vm), StopReason::Breakpoint(0x208));
+        debugger.execute(&mut vm, "d 208", &mut out);
+        assert!(out.is_empty());
+        assert_eq!(debugger.breakpoints().count(), 0);
+    }
+
+    #[test]
+    fn addresses_past_the_memory_are_rejected() {
+        let mut vm = vm();
+        let mut debugger = Debugger::new(10);
+        let mut out = String::new();
+        debugger.execute(&mut vm, "b 10000", &mut out);
+        debugger.execute(&mut vm, "l 1000", &mut out);
+        assert_eq!(out, "no address 10000 in memory\nno address 1000 in memory\n");
+        assert_eq!(debugger.breakpoints().count(), 0);
+        out.clear();
+        debugger.execute(&mut vm, "x ffffffffffffffff 10", &mut out);
+        debugger.execute(&mut vm, "x FFE 10", &mut out);
+        assert_eq!(out, "0FFE: 00 00\n");
+    }
+
+    #[test]
+    fn numbers_are_hexadecimal_unless_prefixed() {
+        assert_eq!(parse_number("2A0"), Some(0x2A0));
+        assert_eq!(parse_number("0x2a0"), Some(0x2A0));
+        assert_eq!(parse_number("#672"), Some(0x2A0));
+        assert_eq!(parse_number("#2A0"), None);
+        assert_eq!(parse_number("zz"), None);
+    }
+}
@@ -0,0 +1,371 @@
+use core::fmt::Write;
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+
+use crate::insts::Instruction;
+
+use crate::platform::Platform;
+
+/// Programs are loaded at this address.
+pub const ROM_START: u16 = 0x200;
+
+/// Mnemonic flavour of the listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// The high level syntax of Octo: `v0 := 5`, `if v1 == v2 then`, `sprite v0 v1 5`.
+    Octo,
+    /// The assembly of Cowgod's reference: `LD V0, #05`, `SNE V1, V2`, `DRW V0, V1, 5`.
+    Classic,
+}
+
+/// Kind of a recovered label, which gives its prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Data,
+    Label,
+    Sub,
+}
+
+/// Walks a ROM from its entry point, following the control flow to tell code
+/// from data, and names every address the program refers to.
+pub struct Disassembler<'a> {
+    rom: &'a [u8],
+    platform: Platform,
+    code: BTreeSet<u16>, // first byte of every instruction reached
+    labels: BTreeMap<u16, LabelKind>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(rom: &'a [u8], platform: Platform) -> Self {
+        let mut disassembler = Disassembler {
+            rom,
+            platform,
+            code: BTreeSet::new(),
+            labels: BTreeMap::new(),
+        };
+        disassembler.analyze();
+        disassembler
+    }
+
+    fn word(&self, addr: u16) -> Option<u16> {
+        let offset = addr.checked_sub(ROM_START)? as usize;
+        let high = *self.rom.get(offset)?;
+        let low = *self.rom.get(offset + 1)?;
+        Some(((high as u16) << 8) | low as u16)
+    }
+
+    fn decode(&self, addr: u16) -> Option<Instruction> {
+        let instruction = Instruction::new(self.word(addr)?);
+        if self.platform.supports(&instruction) {
+            Some(instruction)
+        } else {
+            Some(Instruction::ERROR(self.word(addr)?))
+        }
+    }
+
+    /// Length of the instruction at `addr`, F000 NNNN takes four bytes on XO-CHIP.
+    fn len_at(&self, addr: u16) -> u16 {
+        match self.decode(addr) {
+            Some(Instruction::LongI) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Names `addr`, unless it is outside the ROM: the listing could not
+    /// define the label, it keeps the address as a number.
+    fn add_label(&mut self, addr: u16, kind: LabelKind) {
+        if !(ROM_START as usize..ROM_START as usize + self.rom.len()).contains(&(addr as usize)) {
+            return;
+        }
+        let entry = self.labels.entry(addr).or_insert(kind);
+        *entry = (*entry).max(kind);
+    }
+
+    fn analyze(&mut self) {
+        let mut pending = vec![ROM_START];
+        while let Some(addr) = pending.pop() {
+            if self.code.contains(&addr) {
+                continue;
+            }
+            let Some(instruction) = self.decode(addr) else {
+                continue;
+            };
+            if let Instruction::ERROR(_) = instruction {
+                // not code after all, leave it to the data
+                continue;
+            }
+            if instruction == Instruction::LongI && self.word(addr.wrapping_add(2)).is_none() {
+                continue;
+            }
+            self.code.insert(addr);
+            let next = addr.wrapping_add(self.len_at(addr));
+
+            match instruction {
+                Instruction::Jump(target) => {
+                    self.add_label(target, LabelKind::Label);
+                    pending.push(target);
+                }
+                Instruction::Jump2(target) => {
+                    // usually a table of jumps, we can only follow its first entry
+                    self.add_label(target, LabelKind::Label);
+                    pending.push(target);
+                }
+                Instruction::Call(target) => {
+                    self.add_label(target, LabelKind::Sub);
+                    pending.push(target);
+                    pending.push(next);
+                }
+                Instruction::Ret | Instruction::Exit => {}
+                Instruction::SkipNextInstruction(_, _)
+                | Instruction::NSkipNextInstruction(_, _)
+                | Instruction::R2SkipNextInstruction(_, _)
+                | Instruction::NR2SkipNextInstruction(_, _)
+                | Instruction::SkipIfPressed(_)
+                | Instruction::SkipIfNotPressed(_) => {
+                    pending.push(next);
+                    pending.push(next.wrapping_add(self.len_at(next)));
+                }
+                Instruction::SetI(target) => {
+                    self.add_label(target, LabelKind::Data);
+                    pending.push(next);
+                }
+                Instruction::LongI => {
+                    if let Some(target) = self.word(addr.wrapping_add(2)) {
+                        self.add_label(target, LabelKind::Data);
+                    }
+                    pending.push(next);
+                }
+                _ => pending.push(next),
+            }
+        }
+    }
+
+    /// Whether `addr` starts an instruction reachable from the entry point.
+    pub fn is_code(&self, addr: u16) -> bool {
+        self.code.contains(&addr)
+    }
+
+    /// Name of the label at `addr`, if the program refers to it.
+    pub fn label(&self, addr: u16) -> Option<String> {
+        let prefix = match self.labels.get(&addr)? {
+            LabelKind::Data => "data",
+            LabelKind::Label => "label",
+            LabelKind::Sub => "sub",
+        };
+        Some(format!("{}_{:03X}", prefix, addr))
+    }
+
+    /// The whole ROM as source, code and data interleaved in address order.
+    pub fn listing(&self, syntax: Syntax) -> String {
+        let mut out = String::new();
+        let end = ROM_START as usize + self.rom.len();
+        let mut addr = ROM_START as usize;
+        while addr < end {
+            if let Some(label) = self.label(addr as u16) {
+                match syntax {
+                    Syntax::Octo => {
+                        let _ = writeln!(out, ": {}", label);
+                    }
+                    Syntax::Classic => {
+                        let _ = writeln!(out, "{}:", label);
+                    }
+                }
+            }
+            if self.is_code(addr as u16) {
+                let instruction = self.decode(addr as u16).unwrap();
+                let long = self.word((addr + 2) as u16).unwrap_or(0);
+                let text = format_instruction(&instruction, long, syntax, |target| self.label(target));
+                let _ = writeln!(out, "    {}", text);
+                addr += self.len_at(addr as u16) as usize;
+                continue;
+            }
+            // data runs until the next instruction or label, 8 bytes per line
+            let mut bytes = vec![];
+            while addr < end && bytes.len() < 8 {
+                bytes.push(self.rom[addr - ROM_START as usize]);
+                addr += 1;
+                if self.is_code(addr as u16) || self.labels.contains_key(&(addr as u16)) {
+                    break;
+                }
+            }
+            let _ = writeln!(out, "    {}", format_data(&bytes, syntax));
+        }
+        out
+    }
+}
+
+fn format_data(bytes: &[u8], syntax: Syntax) -> String {
+    let mut out = String::new();
+    match syntax {
+        Syntax::Octo => {
+            for (n, byte) in bytes.iter().enumerate() {
+                let sep = if n == 0 { "" } else { " " };
+                let _ = write!(out, "{}0x{:02X}", sep, byte);
+            }
+        }
+        Syntax::Classic => {
+            out.push_str("DB");
+            for (n, byte) in bytes.iter().enumerate() {
+                let sep = if n == 0 { " " } else { ", " };
+                let _ = write!(out, "{}#{:02X}", sep, byte);
+            }
+        }
+    }
+    out
+}
+
+/// Text of one instruction. `long` is the word following `LongI`, `label`
+/// names the addresses the instruction refers to, when they have a name.
+pub fn format_instruction(
+    instruction: &Instruction,
+    long: u16,
+    syntax: Syntax,
+    label: impl Fn(u16) -> Option<String>,
+) -> String {
+    let addr = |target: u16| match syntax {
+        Syntax::Octo => label(target).unwrap_or_else(|| format!("0x{:03X}", target)),
+        Syntax::Classic => label(target).unwrap_or_else(|| format!("#{:03X}", target)),
+    };
+    match syntax {
+        Syntax::Octo => format_octo(instruction, long, addr),
+        Syntax::Classic => format_classic(instruction, long, addr),
+    }
+}
+
+fn format_octo(instruction: &Instruction, long: u16, addr: impl Fn(u16) -> String) -> String {
+    match *instruction {
+        Instruction::ClearScreen => "clear".into(),
+        Instruction::Ret => "return".into(),
+        Instruction::Jump(a) => format!("jump {}", addr(a)),
+        Instruction::Call(a) => format!(":call {}", addr(a)),
+        Instruction::SetRegister(x, n) => format!("v{:X} := 0x{:02X}", x, n),
+        Instruction::AddRegister(x, n) => format!("v{:X} += 0x{:02X}", x, n),
+        Instruction::SetI(a) => format!("i := {}", addr(a)),
+        Instruction::Draw(x, y, n) => format!("sprite v{:X} v{:X} {}", x, y, n),
+        // Octo's `if` runs the next instruction when the condition holds, the
+        // skips do the opposite
+        Instruction::SkipNextInstruction(x, n) => format!("if v{:X} != 0x{:02X} then", x, n),
+        Instruction::NSkipNextInstruction(x, n) => format!("if v{:X} == 0x{:02X} then", x, n),
+        Instruction::R2SkipNextInstruction(x, y) => format!("if v{:X} != v{:X} then", x, y),
+        Instruction::NR2SkipNextInstruction(x, y) => format!("if v{:X} == v{:X} then", x, y),
+        Instruction::STORE(x, y) => format!("v{:X} := v{:X}", x, y),
+        Instruction::OR(x, y) => format!("v{:X} |= v{:X}", x, y),
+        Instruction::AND(x, y) => format!("v{:X} &= v{:X}", x, y),
+        Instruction::XOR(x, y) => format!("v{:X} ^= v{:X}", x, y),
+        Instruction::ADD(x, y) => format!("v{:X} += v{:X}", x, y),
+        Instruction::SUB(x, y) => format!("v{:X} -= v{:X}", x, y),
+        Instruction::SHR(x, y) => format!("v{:X} >>= v{:X}", x, y),
+        Instruction::SUBN(x, y) => format!("v{:X} =- v{:X}", x, y),
+        Instruction::SHL(x, y) => format!("v{:X} <<= v{:X}", x, y),
+        Instruction::ReadDelay(x) => format!("v{:X} := delay", x),
+        Instruction::WaitKey(x) => format!("v{:X} := key", x),
+        Instruction::SetDelay(x) => format!("delay := v{:X}", x),
+        Instruction::SetSound(x) => format!("buzzer := v{:X}", x),
+        Instruction::AddI(x) => format!("i += v{:X}", x),
+        Instruction::SpriteDigit(x) => format!("i := hex v{:X}", x),
+        Instruction::StoreBCD(x) => format!("bcd v{:X}", x),
+        Instruction::StoreRegisters(x) => format!("save v{:X}", x),
+        Instruction::ReadRegisters(x) => format!("load v{:X}", x),
+        Instruction::SkipIfPressed(x) => format!("if v{:X} -key then", x),
+        Instruction::SkipIfNotPressed(x) => format!("if v{:X} key then", x),
+        Instruction::Random(x, n) => format!("v{:X} := random 0x{:02X}", x, n),
+        Instruction::Jump2(a) => format!("jump0 {}", addr(a)),
+        Instruction::ScrollDown(n) => format!("scroll-down {}", n),
+        Instruction::ScrollRight => "scroll-right".into(),
+        Instruction::ScrollLeft => "scroll-left".into(),
+        Instruction::Exit => "exit".into(),
+        Instruction::LowRes => "lores".into(),
+        Instruction::HighRes => "hires".into(),
+        Instruction::BigSpriteDigit(x) => format!("i := bighex v{:X}", x),
+        Instruction::StoreFlags(x) => format!("saveflags v{:X}", x),
+        Instruction::ReadFlags(x) => format!("loadflags v{:X}", x),
+        Instruction::ScrollUp(n) => format!("scroll-up {}", n),
+        Instruction::SaveRange(x, y) => format!("save v{:X} - v{:X}", x, y),
+        Instruction::LoadRange(x, y) => format!("load v{:X} - v{:X}", x, y),
+        Instruction::LongI => format!("i := long {}", addr(long)),
+        Instruction::SelectPlane(n) => format!("plane {}", n),
+        Instruction::LoadAudio => "audio".into(),
+        Instruction::SetPitch(x) => format!("pitch := v{:X}", x),
+        Instruction::ERROR(opcode) => format!("0x{:02X} 0x{:02X}", opcode >> 8, opcode & 0xFF),
+    }
+}
+
+/// The canonical text of [`Instruction`], with labels in place of addresses.
+fn format_classic(instruction: &Instruction, long: u16, addr: impl Fn(u16) -> String) -> String {
+    match *instruction {
+        Instruction::Jump(a) => format!("JP {}", addr(a)),
+        Instruction::Call(a) => format!("CALL {}", addr(a)),
+        Instruction::SetI(a) => format!("LD I, {}", addr(a)),
+        Instruction::Jump2(a) => format!("JP V0, {}", addr(a)),
+        Instruction::LongI => format!("LD I, LONG {}", addr(long)),
+        _ => instruction.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // i := 0x206, sprite v0 v1 5, jump to itself, then a 5 byte sprite
+    const SPRITE: [u8; 11] = [0xA2, 0x06, 0xD0, 0x15, 0x12, 0x04, 0xF0, 0x90, 0x90, 0x90, 0xF0];
+
+    #[test]
+    fn sprites_are_data() {
+        let disassembler = Disassembler::new(&SPRITE, Platform::Chip8);
+        assert!([0x200, 0x202, 0x204].iter().all(|addr| disassembler.is_code(*addr)));
+        assert!((0x206..0x20B).all(|addr| !disassembler.is_code(addr)));
+        assert_eq!(disassembler.label(0x206).as_deref(), Some("data_206"));
+        assert_eq!(disassembler.label(0x204).as_deref(), Some("label_204"));
+        assert_eq!(disassembler.label(0x202), None);
+    }
+
+    #[test]
+    fn listings() {
+        let disassembler = Disassembler::new(&SPRITE, Platform::Chip8);
+        assert_eq!(
+            disassembler.listing(Syntax::Octo),
+            "    i := data_206\n    sprite v0 v1 5\n: label_204\n    jump label_204\n: data_206\n    0xF0 0x90 0x90 0x90 0xF0\n"
+        );
+        assert_eq!(
+            disassembler.listing(Syntax::Classic),
+            "    LD I, data_206\n    DRW V0, V1, 5\nlabel_204:\n    JP label_204\ndata_206:\n    DB #F0, #90, #90, #90, #F0\n"
+        );
+    }
+
+    #[test]
+    fn calls_return_to_the_next_instruction() {
+        // call 0x205, jump to itself, a byte never reached, then the subroutine
+        let rom = [0x22, 0x05, 0x12, 0x02, 0xFF, 0x00, 0xEE];
+        let disassembler = Disassembler::new(&rom, Platform::Chip8);
+        assert!(disassembler.is_code(0x202) && disassembler.is_code(0x205));
+        assert!(!disassembler.is_code(0x204));
+        assert_eq!(disassembler.label(0x205).as_deref(), Some("sub_205"));
+    }
+
+    #[test]
+    fn skips_step_over_long_i() {
+        // if v0 == 0 skip the 4 bytes of i := long 0x208, jump to itself, data
+        let rom = [0x40, 0x00, 0xF0, 0x00, 0x02, 0x08, 0x12, 0x06, 0xAA];
+        let disassembler = Disassembler::new(&rom, Platform::XoChip);
+        assert!([0x200, 0x202, 0x206].iter().all(|addr| disassembler.is_code(*addr)));
+        assert!(!disassembler.is_code(0x204));
+        assert_eq!(disassembler.label(0x208).as_deref(), Some("data_208"));
+        // without XO-CHIP F000 isn't an instruction, so nothing after the skip is code
+        let disassembler = Disassembler::new(&rom, Platform::Chip8);
+        assert!(!disassembler.is_code(0x202));
+    }
+
+    #[test]
+    fn addresses_outside_the_rom_stay_numbers() {
+        // i := 0x300, bcd v0, i := long 0x1234, jump 0x400, none of them in the ROM
+        let rom = [0xA3, 0x00, 0xF0, 0x33, 0xF0, 0x00, 0x12, 0x34, 0x14, 0x00];
+        let disassembler = Disassembler::new(&rom, Platform::XoChip);
+        assert_eq!(disassembler.label(0x300), None);
+        let source = disassembler.listing(Syntax::Octo);
+        assert_eq!(source, "    i := 0x300\n    bcd v0\n    i := long 0x1234\n    jump 0x400\n");
+        assert_eq!(crate::assembler::assemble(&source).unwrap(), rom);
+    }
+}
@@ -0,0 +1,329 @@
+use core::ops::{BitAnd, BitOr, BitXor, Not, Shl, Shr};
+
+use alloc::vec::Vec;
+
+/// Size of the screen in the SUPER-CHIP high resolution mode.
+pub const MAX_WIDTH: usize = 128;
+pub const MAX_HEIGHT: usize = 64;
+
+/// Past this many dirty rectangles they are merged into one.
+const MAX_DIRTY: usize = 8;
+
+/// An area of the screen, in CHIP-8 pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Rect { x, y, width, height }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// The smallest rectangle holding both.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    /// Whether the two overlap or share an edge.
+    fn touches(&self, other: &Rect) -> bool {
+        self.x <= other.x + other.width
+            && other.x <= self.x + self.width
+            && self.y <= other.y + other.height
+            && other.y <= self.y + self.height
+    }
+
+    fn clip(&self, width: usize, height: usize) -> Rect {
+        let (x, y) = (self.x.min(width), self.y.min(height));
+        Rect::new(x, y, self.width.min(width - x), self.height.min(height - y))
+    }
+}
+
+/// A line of the screen on one plane, the leftmost pixel in the top bit.
+trait Row:
+    Copy
+    + PartialEq
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+    + Not<Output = Self>
+    + Shl<usize, Output = Self>
+    + Shr<usize, Output = Self>
+{
+    const ZERO: Self;
+    const BITS: usize;
+    fn from_u16(bits: u16) -> Self;
+}
+
+impl Row for u64 {
+    const ZERO: Self = 0;
+    const BITS: usize = 64;
+    fn from_u16(bits: u16) -> Self {
+        bits as u64
+    }
+}
+
+impl Row for u128 {
+    const ZERO: Self = 0;
+    const BITS: usize = 128;
+    fn from_u16(bits: u16) -> Self {
+        bits as u128
+    }
+}
+
+fn get_bit<R: Row>(rows: &[R], x: usize, y: usize) -> bool {
+    (rows[y] << x) >> (R::BITS - 1) != R::ZERO
+}
+
+fn set_bit<R: Row>(rows: &mut [R], x: usize, y: usize, on: bool) {
+    let bit = R::from_u16(1) << (R::BITS - 1 - x);
+    rows[y] = if on { rows[y] | bit } else { rows[y] & !bit };
+}
+
+/// XORs the `width` low bits of `bits` into line `y` from column `x`, what
+/// goes past the right edge wraps around or is lost. True on a collision.
+fn draw_row<R: Row>(rows: &mut [R], x: usize, y: usize, bits: u16, width: usize, wrap: bool) -> bool {
+    let aligned = R::from_u16(bits) << (R::BITS - width);
+    let mut mask = aligned >> x;
+    if wrap && x > 0 {
+        mask = mask | aligned << (R::BITS - x);
+    }
+    let collision = rows[y] & mask != R::ZERO;
+    rows[y] = rows[y] ^ mask;
+    collision
+}
+
+fn scroll_rows<R: Row>(rows: &mut [R], dx: isize, dy: isize) {
+    let old: Vec<R> = rows.to_vec();
+    for (y, row) in rows.iter_mut().enumerate() {
+        let from = usize::try_from(y as isize - dy).ok().and_then(|from| old.get(from));
+        let moved = from.copied().unwrap_or(R::ZERO);
+        *row = if dx >= 0 { moved >> dx as usize } else { moved << dx.unsigned_abs() };
+    }
+}
+
+/// The screen of the VM, with the areas changed since it was last presented.
+///
+/// Every plane is stored as one integer per line, so a sprite row is drawn
+/// with a shift and a XOR, and collides when the AND isn't zero.
+pub struct Framebuffer {
+    lores_rows: [[u64; MAX_HEIGHT / 2]; 2],
+    hires_rows: [[u128; MAX_HEIGHT]; 2],
+    hires: bool,
+    dirty: Vec<Rect>,
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Framebuffer {
+            lores_rows: [[0; MAX_HEIGHT / 2]; 2],
+            hires_rows: [[0; MAX_HEIGHT]; 2],
+            hires: false,
+            dirty: Vec::new(),
+        }
+    }
+
+    /// Width of the screen in the current resolution.
+    pub fn width(&self) -> usize {
+        if self.hires { MAX_WIDTH } else { MAX_WIDTH / 2 }
+    }
+
+    /// Height of the screen in the current resolution.
+    pub fn height(&self) -> usize {
+        if self.hires { MAX_HEIGHT } else { MAX_HEIGHT / 2 }
+    }
+
+    pub fn hires(&self) -> bool {
+        self.hires
+    }
+
+    /// The plane bits of a pixel: 0 is the background, 1 the first plane,
+    /// 2 the second XO-CHIP plane and 3 both of them. 0 outside of the screen.
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        if x >= self.width() || y >= self.height() {
+            return 0;
+        }
+        (0..2).fold(0, |color, plane| {
+            let lit = if self.hires {
+                get_bit(&self.hires_rows[plane], x, y)
+            } else {
+                get_bit(&self.lores_rows[plane], x, y)
+            };
+            color | (lit as u8) << plane
+        })
+    }
+
+    /// Line `y` of a plane (0 or 1), the pixel at `x` is bit `width - 1 - x`.
+    pub fn row(&self, plane: usize, y: usize) -> u128 {
+        if self.hires { self.hires_rows[plane][y] } else { self.lores_rows[plane][y] as u128 }
+    }
+
+    /// Areas changed since the last call to [`Framebuffer::take_dirty`].
+    pub fn dirty(&self) -> &[Rect] {
+        &self.dirty
+    }
+
+    pub(crate) fn take_dirty(&mut self) -> Vec<Rect> {
+        core::mem::take(&mut self.dirty)
+    }
+
+    /// Gives back the list taken by [`Framebuffer::take_dirty`], emptied, to reuse its memory.
+    pub(crate) fn recycle_dirty(&mut self, mut dirty: Vec<Rect>) {
+        dirty.clear();
+        self.dirty = dirty;
+    }
+
+    /// Switches between 64x32 and 128x64, the screen is blank afterwards.
+    pub(crate) fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.lores_rows = [[0; MAX_HEIGHT / 2]; 2];
+        self.hires_rows = [[0; MAX_HEIGHT]; 2];
+        self.mark_all();
+    }
+
+    pub(crate) fn set(&mut self, x: usize, y: usize, color: u8) {
+        if x >= self.width() || y >= self.height() {
+            return;
+        }
+        for plane in 0..2 {
+            let on = color & (1 << plane) != 0;
+            if self.hires {
+                set_bit(&mut self.hires_rows[plane], x, y, on);
+            } else {
+                set_bit(&mut self.lores_rows[plane], x, y, on);
+            }
+        }
+    }
+
+    /// Clears the given planes.
+    pub(crate) fn clear(&mut self, planes: u8) {
+        for plane in 0..2 {
+            if planes & (1 << plane) != 0 {
+                self.lores_rows[plane] = [0; MAX_HEIGHT / 2];
+                self.hires_rows[plane] = [0; MAX_HEIGHT];
+            }
+        }
+        self.mark_all();
+    }
+
+    /// XORs a sprite row (the `width` low bits of `bits`) on `plane` (0 or 1)
+    /// at (x, y), past the right edge it wraps around or is clipped. Returns
+    /// true when a lit pixel was turned off, which is a collision for `Draw`.
+    /// The caller marks the area.
+    pub(crate) fn draw_row(&mut self, plane: usize, x: usize, y: usize, bits: u16, width: usize, wrap: bool) -> bool {
+        if self.hires {
+            draw_row(&mut self.hires_rows[plane], x, y, bits, width, wrap)
+        } else {
+            draw_row(&mut self.lores_rows[plane], x, y, bits, width, wrap)
+        }
+    }
+
+    /// Moves the given planes by (dx, dy), what comes from outside of the screen is blank.
+    pub(crate) fn scroll(&mut self, dx: isize, dy: isize, planes: u8) {
+        for plane in 0..2 {
+            if planes & (1 << plane) != 0 {
+                if self.hires {
+                    scroll_rows(&mut self.hires_rows[plane], dx, dy);
+                } else {
+                    scroll_rows(&mut self.lores_rows[plane], dx, dy);
+                }
+            }
+        }
+        self.mark_all();
+    }
+
+    /// Records that `rect` changed, the part outside of the screen is ignored.
+    pub(crate) fn mark(&mut self, rect: Rect) {
+        let mut rect = rect.clip(self.width(), self.height());
+        if rect.is_empty() {
+            return;
+        }
+        while let Some(n) = self.dirty.iter().position(|other| other.touches(&rect)) {
+            rect = rect.union(&self.dirty.swap_remove(n));
+        }
+        self.dirty.push(rect);
+        if self.dirty.len() > MAX_DIRTY {
+            let all = self.dirty.iter().fold(rect, |all, other| all.union(other));
+            self.dirty.clear();
+            self.dirty.push(all);
+        }
+    }
+
+    /// Records that the whole screen changed.
+    pub(crate) fn mark_all(&mut self) {
+        self.dirty.clear();
+        self.dirty.push(Rect::new(0, 0, self.width(), self.height()));
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Framebuffer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn touching_rects_merge() {
+        let mut frame = Framebuffer::new();
+        frame.mark(Rect::new(0, 0, 8, 5));
+        frame.mark(Rect::new(8, 0, 8, 5));
+        frame.mark(Rect::new(40, 20, 8, 5));
+        assert_eq!(frame.dirty(), [Rect::new(0, 0, 16, 5), Rect::new(40, 20, 8, 5)]);
+    }
+
+    #[test]
+    fn rects_are_clipped_to_the_screen() {
+        let mut frame = Framebuffer::new();
+        frame.mark(Rect::new(60, 30, 8, 5));
+        frame.mark(Rect::new(70, 0, 8, 5));
+        assert_eq!(frame.dirty(), [Rect::new(60, 30, 4, 2)]);
+    }
+
+    #[test]
+    fn too_many_rects_become_one() {
+        let mut frame = Framebuffer::new();
+        for n in 0..=MAX_DIRTY {
+            frame.mark(Rect::new(n * 7, n * 3, 1, 1));
+        }
+        assert_eq!(frame.dirty(), [Rect::new(0, 0, 7 * MAX_DIRTY + 1, 3 * MAX_DIRTY + 1)]);
+    }
+
+    #[test]
+    fn rows_hold_the_leftmost_pixel_in_the_top_bit() {
+        let mut frame = Framebuffer::new();
+        assert!(!frame.draw_row(0, 60, 2, 0x8F, 8, true));
+        assert_eq!(frame.row(0, 2), 0xF000_0000_0000_0008);
+        assert!(frame.draw_row(0, 0, 2, 0x80, 8, false));
+        assert_eq!((frame.get(0, 2), frame.get(1, 2), frame.get(60, 2), frame.get(63, 2)), (0, 1, 1, 0));
+        frame.set_hires(true);
+        frame.draw_row(1, 120, 63, 0xFFFF, 16, false);
+        assert_eq!(frame.row(1, 63), 0xFF);
+        assert_eq!((frame.get(127, 63), frame.get(0, 63)), (2, 0));
+    }
+
+    #[test]
+    fn scroll_moves_the_selected_planes() {
+        let mut frame = Framebuffer::new();
+        frame.set(0, 0, 3);
+        frame.set(63, 31, 1);
+        frame.scroll(4, 2, 1);
+        assert_eq!((frame.get(0, 0), frame.get(4, 2)), (2, 1));
+        assert_eq!(frame.get(63, 31), 0);
+        frame.scroll(-4, -2, 3);
+        assert_eq!((frame.get(0, 0), frame.get(60, 29)), (1, 0));
+    }
+}
@@ -0,0 +1,86 @@
+use core::fmt;
+
+use alloc::string::String;
+
+/// Everything that can go wrong while the VM executes a ROM.
+///
+/// Every variant carries the address of the faulting instruction, and the
+/// opcode when it could be fetched, so a frontend can report the error and
+/// inspect the machine afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    /// The opcode does not match any known instruction.
+    UnknownOpcode { pc: u16, opcode: u16 },
+    /// `Ret` was executed with an empty stack.
+    StackUnderflow { pc: u16, opcode: u16 },
+    /// `Call` was executed with a full stack.
+    StackOverflow { pc: u16, opcode: u16 },
+    /// The instruction tried to read or write outside of the memory.
+    MemoryOutOfBounds { pc: u16, opcode: u16, address: usize },
+    /// The program counter points outside of the memory.
+    InvalidPc { pc: u16 },
+}
+
+impl VmError {
+    /// Address of the instruction that failed.
+    pub fn pc(&self) -> u16 {
+        match *self {
+            VmError::UnknownOpcode { pc, .. }
+            | VmError::StackUnderflow { pc, .. }
+            | VmError::StackOverflow { pc, .. }
+            | VmError::MemoryOutOfBounds { pc, .. }
+            | VmError::InvalidPc { pc } => pc,
+        }
+    }
+
+    /// Opcode of the instruction that failed, if it could be fetched.
+    pub fn opcode(&self) -> Option<u16> {
+        match *self {
+            VmError::UnknownOpcode { opcode, .. }
+            | VmError::StackUnderflow { opcode, .. }
+            | VmError::StackOverflow { opcode, .. }
+            | VmError::MemoryOutOfBounds { opcode, .. } => Some(opcode),
+            VmError::InvalidPc { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            VmError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:03X}", opcode, pc)
+            }
+            VmError::StackUnderflow { pc, opcode } => {
+                write!(f, "return with an empty stack ({:04X} at {:03X})", opcode, pc)
+            }
+            VmError::StackOverflow { pc, opcode } => {
+                write!(f, "call with a full stack ({:04X} at {:03X})", opcode, pc)
+            }
+            VmError::MemoryOutOfBounds { pc, opcode, address } => {
+                write!(f, "memory access at {:X} out of bounds ({:04X} at {:03X})", address, opcode, pc)
+            }
+            VmError::InvalidPc { pc } => {
+                write!(f, "program counter {:X} is outside of the memory", pc)
+            }
+        }
+    }
+}
+
+/// Why a line of text is not an [`Instruction`](crate::insts::Instruction).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseInstructionError {
+    /// The first word is not a mnemonic.
+    UnknownMnemonic(String),
+    /// The mnemonic exists but not with these operands, holds the whole line.
+    InvalidOperands(String),
+}
+
+impl fmt::Display for ParseInstructionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseInstructionError::UnknownMnemonic(mnemonic) => write!(f, "unknown mnemonic {}", mnemonic),
+            ParseInstructionError::InvalidOperands(line) => write!(f, "invalid operands in '{}'", line),
+        }
+    }
+}
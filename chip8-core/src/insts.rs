@@ -0,0 +1,577 @@
+use core::fmt;
+use core::str::FromStr;
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::error::ParseInstructionError;
+
+/// Registers and nibbles are `u8`, so are byte immediates, addresses are `u16`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    ClearScreen,
+    Jump(u16), // addr
+    SetRegister(u8, u8),
+    AddRegister(u8, u8),
+    SetI(u16),
+    Draw(u8, u8, u8),
+    Call(u16), // addr
+    Ret,
+    SkipNextInstruction(u8, u8),
+    NSkipNextInstruction(u8, u8),
+    R2SkipNextInstruction(u8, u8),
+    NR2SkipNextInstruction(u8, u8),
+    STORE(u8, u8),
+    OR(u8, u8),
+    AND(u8, u8),
+    XOR(u8, u8),
+    ADD(u8, u8),
+    SUB(u8, u8),
+    SHR(u8, u8),
+    SUBN(u8, u8),
+    SHL(u8, u8),
+    ReadDelay(u8),
+    WaitKey(u8),
+    SetDelay(u8),
+    SetSound(u8),
+    AddI(u8),
+    SpriteDigit(u8),
+    #[allow(dead_code)]
+    StoreBCD(u8),
+    #[allow(dead_code)]
+    StoreRegisters(u8),
+    #[allow(dead_code)]
+    ReadRegisters(u8),
+    SkipIfPressed(u8),
+    SkipIfNotPressed(u8),
+    Random(u8, u8),
+    Jump2(u16),
+
+    // SUPER-CHIP 1.1
+    ScrollDown(u8),
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowRes,
+    HighRes,
+    BigSpriteDigit(u8),
+    StoreFlags(u8),
+    ReadFlags(u8),
+
+    // XO-CHIP
+    ScrollUp(u8),
+    SaveRange(u8, u8),
+    LoadRange(u8, u8),
+    LongI, // the address is in the next two bytes
+    SelectPlane(u8),
+    LoadAudio, // 16 bytes at I
+    SetPitch(u8),
+
+    ERROR(u16), // unknown opcode
+}
+
+impl Instruction {
+    pub fn new(value: u16) -> Instruction {
+        let lower1 = (value & 0x000F) as u8;
+        let upper1 = ((value & 0x00F0) >> 4) as u8;
+        let lower2 = ((value & 0x0F00) >> 8) as u8;
+        let upper2 = ((value & 0xF000) >> 12) as u8;
+        let addr = value & 0x0FFF;
+        let byte = (value & 0x00FF) as u8;
+
+        match (upper2, lower2, upper1, lower1) {
+            (0x0, 0x0, 0xe, 0x0) => {
+                Instruction::ClearScreen
+            }
+
+            (0x0, 0x0, 0xe, 0xe) => {
+                Instruction::Ret
+            }
+
+            (0x0, 0x0, 0xc, n) => {
+                Instruction::ScrollDown(n)
+            }
+
+            (0x0, 0x0, 0xd, n) => {
+                Instruction::ScrollUp(n)
+            }
+
+            (0x0, 0x0, 0xf, 0xb) => {
+                Instruction::ScrollRight
+            }
+
+            (0x0, 0x0, 0xf, 0xc) => {
+                Instruction::ScrollLeft
+            }
+
+            (0x0, 0x0, 0xf, 0xd) => {
+                Instruction::Exit
+            }
+
+            (0x0, 0x0, 0xf, 0xe) => {
+                Instruction::LowRes
+            }
+
+            (0x0, 0x0, 0xf, 0xf) => {
+                Instruction::HighRes
+            }
+
+            (0x1, _, _, _) => {
+                Instruction::Jump(addr)
+            }
+
+            (0x2, _, _, _) => {
+                Instruction::Call(addr)
+            }
+
+            (0x3, x, _, _) => {
+                Instruction::SkipNextInstruction(x, byte)
+            }
+
+            (0x4, x, _, _) => {
+                Instruction::NSkipNextInstruction(x, byte)
+            }
+
+            (0x5, x, y, 0x0) => {
+                Instruction::R2SkipNextInstruction(x, y)
+            }
+
+            (0x5, x, y, 0x2) => {
+                Instruction::SaveRange(x, y)
+            }
+
+            (0x5, x, y, 0x3) => {
+                Instruction::LoadRange(x, y)
+            }
+
+            (0x6, x, _, _) => {
+                Instruction::SetRegister(x, byte)
+            }
+
+            (0x7, x, _, _) => {
+                Instruction::AddRegister(x, byte)
+            }
+
+            (0x8, x, y, 0x0) => {
+                Instruction::STORE(x, y)
+            }
+
+            (0x8, x, y, 0x1) => {
+                Instruction::OR(x, y)
+            }
+
+            (0x8, x, y, 0x2) => {
+                Instruction::AND(x, y)
+            }
+
+            (0x8, x, y, 0x3) => {
+                Instruction::XOR(x, y)
+            }
+
+            (0x8, x, y, 0x4) => {
+                Instruction::ADD(x, y)
+            }
+
+            (0x8, x, y, 0x5) => {
+                Instruction::SUB(x, y)
+            }
+
+            (0x8, x, y, 0x6) => {
+                Instruction::SHR(x, y)
+            }
+
+            (0x8, x, y, 0x7) => {
+                Instruction::SUBN(x, y)
+            }
+
+            (0x8, x, y, 0xE) => {
+                Instruction::SHL(x, y)
+            }
+
+            (0x9, x, y, 0x0) => {
+                Instruction::NR2SkipNextInstruction(x, y)
+            }
+
+            (0xa, _, _, _) => {
+                Instruction::SetI(addr)
+            }
+
+            (0xb, _, _, _) => {
+                Instruction::Jump2(addr)
+            }
+
+            (0xc, x, _, _) => {
+                Instruction::Random(x, byte)
+            }
+
+            (0xd, x, y, n) => {
+                Instruction::Draw(x, y, n)
+            }
+
+            (0xe, x, 0x9, 0xe) => {
+                Instruction::SkipIfPressed(x)
+            }
+
+            (0xe, x, 0xa, 0x1) => {
+                Instruction::SkipIfNotPressed(x)
+            }
+            
+            (0xf, 0x0, 0x0, 0x0) => {
+                Instruction::LongI
+            }
+
+            (0xf, n, 0x0, 0x1) => {
+                Instruction::SelectPlane(n)
+            }
+
+            (0xf, 0x0, 0x0, 0x2) => {
+                Instruction::LoadAudio
+            }
+
+            (0xf, x, 0x0, 0x7) => {
+                Instruction::ReadDelay(x)
+            }
+
+            (0xf, x, 0x0, 0xa) => {
+                Instruction::WaitKey(x)
+            }
+
+            (0xf, x, 0x1, 0x5) => {
+                Instruction::SetDelay(x)
+            }
+
+            (0xf, x, 0x1, 0x8) => {
+                Instruction::SetSound(x)
+            }
+
+            (0xf, x, 0x1, 0xe) => {
+                Instruction::AddI(x)
+            }
+
+            (0xf, x, 0x2, 0x9) => {
+                Instruction::SpriteDigit(x)
+            }
+
+            (0xf, x, 0x3, 0x0) => {
+                Instruction::BigSpriteDigit(x)
+            }
+
+            (0xf, x, 0x3, 0x3) => {
+                Instruction::StoreBCD(x)
+            }
+
+            (0xf, x, 0x3, 0xa) => {
+                Instruction::SetPitch(x)
+            }
+
+            (0xf, x, 0x5, 0x5) => {
+                Instruction::StoreRegisters(x)
+            }
+
+            (0xf, x, 0x6, 0x5) => {
+                Instruction::ReadRegisters(x)
+            }
+
+            (0xf, x, 0x7, 0x5) => {
+                Instruction::StoreFlags(x)
+            }
+
+            (0xf, x, 0x8, 0x5) => {
+                Instruction::ReadFlags(x)
+            }
+
+            _ => {
+                Instruction::ERROR(value)
+            }
+        }
+    }
+
+    /// The opcode of the instruction, the inverse of [`Instruction::new`].
+    /// `LongI` only encodes its first word, the address follows it.
+    pub fn encode(&self) -> u16 {
+        let xy = |x: u8, y: u8| ((x & 0xF) as u16) << 8 | ((y & 0xF) as u16) << 4;
+        match *self {
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::Jump(addr) => 0x1000 | (addr & 0xFFF),
+            Instruction::Call(addr) => 0x2000 | (addr & 0xFFF),
+            Instruction::SkipNextInstruction(x, val) => 0x3000 | xy(x, 0) | val as u16,
+            Instruction::NSkipNextInstruction(x, val) => 0x4000 | xy(x, 0) | val as u16,
+            Instruction::R2SkipNextInstruction(x, y) => 0x5000 | xy(x, y),
+            Instruction::SaveRange(x, y) => 0x5002 | xy(x, y),
+            Instruction::LoadRange(x, y) => 0x5003 | xy(x, y),
+            Instruction::SetRegister(x, val) => 0x6000 | xy(x, 0) | val as u16,
+            Instruction::AddRegister(x, val) => 0x7000 | xy(x, 0) | val as u16,
+            Instruction::STORE(x, y) => 0x8000 | xy(x, y),
+            Instruction::OR(x, y) => 0x8001 | xy(x, y),
+            Instruction::AND(x, y) => 0x8002 | xy(x, y),
+            Instruction::XOR(x, y) => 0x8003 | xy(x, y),
+            Instruction::ADD(x, y) => 0x8004 | xy(x, y),
+            Instruction::SUB(x, y) => 0x8005 | xy(x, y),
+            Instruction::SHR(x, y) => 0x8006 | xy(x, y),
+            Instruction::SUBN(x, y) => 0x8007 | xy(x, y),
+            Instruction::SHL(x, y) => 0x800E | xy(x, y),
+            Instruction::NR2SkipNextInstruction(x, y) => 0x9000 | xy(x, y),
+            Instruction::SetI(addr) => 0xA000 | (addr & 0xFFF),
+            Instruction::Jump2(addr) => 0xB000 | (addr & 0xFFF),
+            Instruction::Random(x, val) => 0xC000 | xy(x, 0) | val as u16,
+            Instruction::Draw(x, y, n) => 0xD000 | xy(x, y) | (n & 0xF) as u16,
+            Instruction::SkipIfPressed(x) => 0xE09E | xy(x, 0),
+            Instruction::SkipIfNotPressed(x) => 0xE0A1 | xy(x, 0),
+            Instruction::LongI => 0xF000,
+            Instruction::SelectPlane(n) => 0xF001 | xy(n, 0),
+            Instruction::LoadAudio => 0xF002,
+            Instruction::ReadDelay(x) => 0xF007 | xy(x, 0),
+            Instruction::WaitKey(x) => 0xF00A | xy(x, 0),
+            Instruction::SetDelay(x) => 0xF015 | xy(x, 0),
+            Instruction::SetSound(x) => 0xF018 | xy(x, 0),
+            Instruction::AddI(x) => 0xF01E | xy(x, 0),
+            Instruction::SpriteDigit(x) => 0xF029 | xy(x, 0),
+            Instruction::BigSpriteDigit(x) => 0xF030 | xy(x, 0),
+            Instruction::StoreBCD(x) => 0xF033 | xy(x, 0),
+            Instruction::SetPitch(x) => 0xF03A | xy(x, 0),
+            Instruction::StoreRegisters(x) => 0xF055 | xy(x, 0),
+            Instruction::ReadRegisters(x) => 0xF065 | xy(x, 0),
+            Instruction::StoreFlags(x) => 0xF075 | xy(x, 0),
+            Instruction::ReadFlags(x) => 0xF085 | xy(x, 0),
+            Instruction::ScrollDown(n) => 0x00C0 | (n & 0xF) as u16,
+            Instruction::ScrollUp(n) => 0x00D0 | (n & 0xF) as u16,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::ERROR(value) => value,
+        }
+    }
+}
+
+/// Canonical mnemonics, in the classic syntax of Cowgod's reference. Numbers
+/// are hexadecimal with a `#` prefix, except nibble counts which are decimal.
+/// `LD I, LONG` doesn't show its address, it is the word that follows.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::Jump(a) => write!(f, "JP #{:03X}", a),
+            Instruction::Call(a) => write!(f, "CALL #{:03X}", a),
+            Instruction::SetRegister(x, n) => write!(f, "LD V{:X}, #{:02X}", x, n),
+            Instruction::AddRegister(x, n) => write!(f, "ADD V{:X}, #{:02X}", x, n),
+            Instruction::SetI(a) => write!(f, "LD I, #{:03X}", a),
+            Instruction::Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipNextInstruction(x, n) => write!(f, "SE V{:X}, #{:02X}", x, n),
+            Instruction::NSkipNextInstruction(x, n) => write!(f, "SNE V{:X}, #{:02X}", x, n),
+            Instruction::R2SkipNextInstruction(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::NR2SkipNextInstruction(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::STORE(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::OR(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::AND(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::XOR(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::ADD(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::SUB(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::SHR(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SUBN(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::SHL(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::ReadDelay(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::SpriteDigit(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::StoreBCD(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::StoreRegisters(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::ReadRegisters(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::SkipIfPressed(x) => write!(f, "SKP V{:X}", x),
+            Instruction::SkipIfNotPressed(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::Random(x, n) => write!(f, "RND V{:X}, #{:02X}", x, n),
+            Instruction::Jump2(a) => write!(f, "JP V0, #{:03X}", a),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::BigSpriteDigit(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::StoreFlags(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::ReadFlags(x) => write!(f, "LD V{:X}, R", x),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::SaveRange(x, y) => write!(f, "SAVE V{:X}-V{:X}", x, y),
+            Instruction::LoadRange(x, y) => write!(f, "LOAD V{:X}-V{:X}", x, y),
+            Instruction::LongI => write!(f, "LD I, LONG"),
+            Instruction::SelectPlane(n) => write!(f, "PLANE {}", n),
+            Instruction::LoadAudio => write!(f, "AUDIO"),
+            Instruction::SetPitch(x) => write!(f, "PITCH V{:X}", x),
+            Instruction::ERROR(opcode) => write!(f, "DW #{:04X}", opcode),
+        }
+    }
+}
+
+/// One operand of the classic syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand<'a> {
+    Register(u8),
+    Range(u8, u8),
+    Number(u16),
+    Keyword(&'a str),
+}
+
+fn parse_operand_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('V').or_else(|| text.strip_prefix('v'))?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+/// `#2A0` and `0x2A0` are hexadecimal, anything else is decimal.
+fn parse_operand_number(text: &str) -> Option<u16> {
+    match text.strip_prefix('#').or_else(|| text.strip_prefix("0x")).or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn parse_operand(text: &str) -> Operand<'_> {
+    if let Some(x) = parse_operand_register(text) {
+        return Operand::Register(x);
+    }
+    if let Some((x, y)) = text.split_once('-') {
+        if let (Some(x), Some(y)) = (parse_operand_register(x.trim()), parse_operand_register(y.trim())) {
+            return Operand::Range(x, y);
+        }
+    }
+    match parse_operand_number(text) {
+        Some(n) => Operand::Number(n),
+        None => Operand::Keyword(text),
+    }
+}
+
+/// Parses the text printed by [`Instruction`]'s `Display`, mnemonics and
+/// keywords are case-insensitive.
+impl FromStr for Instruction {
+    type Err = ParseInstructionError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let upper = text.trim().to_uppercase();
+        let (mnemonic, rest) = upper.split_once(char::is_whitespace).unwrap_or((&upper, ""));
+        let operands: Vec<Operand> = if rest.trim().is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',').map(|operand| parse_operand(operand.trim())).collect()
+        };
+
+        use Operand::{Keyword as K, Number as N, Range as R, Register as V};
+        let instruction = match (mnemonic, operands.as_slice()) {
+            ("CLS", []) => Instruction::ClearScreen,
+            ("RET", []) => Instruction::Ret,
+            ("JP", [N(a)]) if *a <= 0xFFF => Instruction::Jump(*a),
+            ("JP", [V(0), N(a)]) if *a <= 0xFFF => Instruction::Jump2(*a),
+            ("CALL", [N(a)]) if *a <= 0xFFF => Instruction::Call(*a),
+            ("SE", [V(x), N(n)]) if *n <= 0xFF => Instruction::SkipNextInstruction(*x, *n as u8),
+            ("SE", [V(x), V(y)]) => Instruction::R2SkipNextInstruction(*x, *y),
+            ("SNE", [V(x), N(n)]) if *n <= 0xFF => Instruction::NSkipNextInstruction(*x, *n as u8),
+            ("SNE", [V(x), V(y)]) => Instruction::NR2SkipNextInstruction(*x, *y),
+            ("LD", [V(x), N(n)]) if *n <= 0xFF => Instruction::SetRegister(*x, *n as u8),
+            ("LD", [V(x), V(y)]) => Instruction::STORE(*x, *y),
+            ("LD", [V(x), K("DT")]) => Instruction::ReadDelay(*x),
+            ("LD", [V(x), K("K")]) => Instruction::WaitKey(*x),
+            ("LD", [V(x), K("[I]")]) => Instruction::ReadRegisters(*x),
+            ("LD", [V(x), K("R")]) => Instruction::ReadFlags(*x),
+            ("LD", [K("I"), N(a)]) if *a <= 0xFFF => Instruction::SetI(*a),
+            ("LD", [K("I"), K("LONG")]) => Instruction::LongI,
+            ("LD", [K("DT"), V(x)]) => Instruction::SetDelay(*x),
+            ("LD", [K("ST"), V(x)]) => Instruction::SetSound(*x),
+            ("LD", [K("F"), V(x)]) => Instruction::SpriteDigit(*x),
+            ("LD", [K("HF"), V(x)]) => Instruction::BigSpriteDigit(*x),
+            ("LD", [K("B"), V(x)]) => Instruction::StoreBCD(*x),
+            ("LD", [K("[I]"), V(x)]) => Instruction::StoreRegisters(*x),
+            ("LD", [K("R"), V(x)]) => Instruction::StoreFlags(*x),
+            ("ADD", [V(x), N(n)]) if *n <= 0xFF => Instruction::AddRegister(*x, *n as u8),
+            ("ADD", [V(x), V(y)]) => Instruction::ADD(*x, *y),
+            ("ADD", [K("I"), V(x)]) => Instruction::AddI(*x),
+            ("OR", [V(x), V(y)]) => Instruction::OR(*x, *y),
+            ("AND", [V(x), V(y)]) => Instruction::AND(*x, *y),
+            ("XOR", [V(x), V(y)]) => Instruction::XOR(*x, *y),
+            ("SUB", [V(x), V(y)]) => Instruction::SUB(*x, *y),
+            ("SHR", [V(x), V(y)]) => Instruction::SHR(*x, *y),
+            ("SUBN", [V(x), V(y)]) => Instruction::SUBN(*x, *y),
+            ("SHL", [V(x), V(y)]) => Instruction::SHL(*x, *y),
+            ("RND", [V(x), N(n)]) if *n <= 0xFF => Instruction::Random(*x, *n as u8),
+            ("DRW", [V(x), V(y), N(n)]) if *n <= 0xF => Instruction::Draw(*x, *y, *n as u8),
+            ("SKP", [V(x)]) => Instruction::SkipIfPressed(*x),
+            ("SKNP", [V(x)]) => Instruction::SkipIfNotPressed(*x),
+            ("SCD", [N(n)]) if *n <= 0xF => Instruction::ScrollDown(*n as u8),
+            ("SCU", [N(n)]) if *n <= 0xF => Instruction::ScrollUp(*n as u8),
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::LowRes,
+            ("HIGH", []) => Instruction::HighRes,
+            ("SAVE", [R(x, y)]) => Instruction::SaveRange(*x, *y),
+            ("LOAD", [R(x, y)]) => Instruction::LoadRange(*x, *y),
+            ("PLANE", [N(n)]) if *n <= 0xF => Instruction::SelectPlane(*n as u8),
+            ("AUDIO", []) => Instruction::LoadAudio,
+            ("PITCH", [V(x)]) => Instruction::SetPitch(*x),
+            ("DW", [N(opcode)]) => Instruction::ERROR(*opcode),
+            (
+                "CLS" | "RET" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD" | "OR" | "AND" | "XOR" | "SUB" | "SHR"
+                | "SUBN" | "SHL" | "RND" | "DRW" | "SKP" | "SKNP" | "SCD" | "SCU" | "SCR" | "SCL" | "EXIT" | "LOW"
+                | "HIGH" | "SAVE" | "LOAD" | "PLANE" | "AUDIO" | "PITCH" | "DW",
+                _,
+            ) => return Err(ParseInstructionError::InvalidOperands(text.to_string())),
+            _ => return Err(ParseInstructionError::UnknownMnemonic(mnemonic.to_string())),
+        };
+        Ok(instruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_opcode_round_trips() {
+        for opcode in 0..=0xFFFF {
+            let instruction = Instruction::new(opcode);
+            assert_eq!(instruction.encode(), opcode, "{:04X} decodes to {:?}", opcode, instruction);
+        }
+    }
+
+    #[test]
+    fn error_keeps_the_raw_opcode() {
+        for opcode in 0..=0xFFFF {
+            if let Instruction::ERROR(raw) = Instruction::new(opcode) {
+                assert_eq!(raw, opcode);
+            }
+        }
+        assert_eq!(Instruction::new(0x5AB1), Instruction::ERROR(0x5AB1));
+        assert_eq!(Instruction::new(0xFFFF), Instruction::ERROR(0xFFFF));
+    }
+
+    #[test]
+    fn every_opcode_round_trips_through_text() {
+        for opcode in 0..=0xFFFF {
+            let instruction = Instruction::new(opcode);
+            let text = instruction.to_string();
+            let parsed: Instruction = text.parse().unwrap_or_else(|e| panic!("{:04X} {}: {}", opcode, text, e));
+            assert_eq!(parsed, instruction, "{}", text);
+            assert_eq!(parsed.encode(), opcode, "{}", text);
+        }
+    }
+
+    #[test]
+    fn parses_loose_text() {
+        assert_eq!("ld v3, 0x2a".parse(), Ok(Instruction::SetRegister(3, 0x2A)));
+        assert_eq!("  drw  VA, VB, 15 ".parse(), Ok(Instruction::Draw(0xA, 0xB, 15)));
+        assert_eq!("save v1 - v4".parse(), Ok(Instruction::SaveRange(1, 4)));
+        assert_eq!(
+            "JP #1000".parse::<Instruction>(),
+            Err(ParseInstructionError::InvalidOperands("JP #1000".to_string()))
+        );
+        assert_eq!(
+            "MOV V0, V1".parse::<Instruction>(),
+            Err(ParseInstructionError::UnknownMnemonic("MOV".to_string()))
+        );
+    }
+}
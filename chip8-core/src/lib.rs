@@ -0,0 +1,20 @@
+//! CHIP-8, SUPER-CHIP and XO-CHIP emulator core, without any dependency on
+//! the OS: the frontend provides the screen, keyboard, random and time sources.
+#![no_std]
+
+extern crate alloc;
+
+pub mod vm;
+pub mod display;
+pub mod phosphor;
+pub mod audio;
+pub mod insts;
+pub mod error;
+pub mod quirks;
+pub mod platform;
+pub mod scheduler;
+pub mod random;
+pub mod savestate;
+pub mod debugger;
+pub mod disasm;
+pub mod assembler;
@@ -0,0 +1,275 @@
+//! Flicker reduction, between the framebuffer of the VM and the screen.
+//!
+//! CHIP-8 games move a sprite by erasing it with a XOR then drawing it again,
+//! so it is missing from every other frame. [`Phosphor`] turns the plane bits
+//! into colours and keeps the pixels that were just erased on screen a bit
+//! longer, like the phosphor of the CRTs these games were written for.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::display::{Framebuffer, Rect};
+
+/// How long an erased pixel stays visible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Persistence {
+    /// Pixels go dark at once.
+    #[default]
+    Off,
+    /// An erased pixel keeps the colour it had in the previous frame, for
+    /// one frame. Lit pixels show their current colour.
+    Or,
+    /// Erased pixels fade out, keeping `strength` percent (0 to 99) of their
+    /// brightness every frame. Lit pixels show up at once.
+    Decay(u8),
+}
+
+impl Persistence {
+    /// Strength of `decay` when none is given.
+    pub const DEFAULT_STRENGTH: u8 = 60;
+
+    /// `off`, `or`, `decay` or `decay:N` with N the strength.
+    pub fn from_name(name: &str) -> Option<Persistence> {
+        match name.split_once(':') {
+            Some(("decay", strength)) => strength.parse().ok().filter(|s| *s < 100).map(Persistence::Decay),
+            Some(_) => None,
+            None => match name {
+                "off" => Some(Persistence::Off),
+                "or" => Some(Persistence::Or),
+                "decay" => Some(Persistence::Decay(Persistence::DEFAULT_STRENGTH)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Moves every channel of `color` towards `background`, keeping `strength` percent of the difference.
+fn fade(color: u32, background: u32, strength: u8) -> u32 {
+    (0..3).fold(0, |out, channel| {
+        let shift = 8 * channel;
+        let from = ((color >> shift) & 0xFF) as i32;
+        let to = ((background >> shift) & 0xFF) as i32;
+        let mixed = to + (from - to) * strength as i32 / 100;
+        out | (mixed as u32) << shift
+    })
+}
+
+/// The colours to show for a framebuffer, updated once per frame.
+pub struct Phosphor {
+    persistence: Persistence,
+    /// Background, first plane, second plane, both planes, as 0xRRGGBB.
+    palette: [u32; 4],
+    width: usize,
+    height: usize,
+    /// Row-major, what is on screen now.
+    colors: Vec<u32>,
+    /// The colours and plane bits of the previous frame.
+    last_colors: Vec<u32>,
+    last_bits: Vec<u8>,
+    last_dirty: Vec<Rect>,
+    /// Around the pixels that haven't reached their colour yet.
+    fading: Option<Rect>,
+    changed: Vec<Rect>,
+}
+
+impl Phosphor {
+    pub fn new(persistence: Persistence, palette: [u32; 4]) -> Self {
+        Phosphor {
+            persistence,
+            palette,
+            width: 0,
+            height: 0,
+            colors: Vec::new(),
+            last_colors: Vec::new(),
+            last_bits: Vec::new(),
+            last_dirty: Vec::new(),
+            fading: None,
+            changed: Vec::new(),
+        }
+    }
+
+    pub fn persistence(&self) -> Persistence {
+        self.persistence
+    }
+
+    pub fn palette(&self) -> &[u32; 4] {
+        &self.palette
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Colour of a pixel as of the last [`Phosphor::update`], only valid for
+    /// pixels of the [`Phosphor::width`] x [`Phosphor::height`] it has seen:
+    /// none before the first update.
+    pub fn color(&self, x: usize, y: usize) -> u32 {
+        self.colors[y * self.width + x]
+    }
+
+    /// Areas whose colour may have changed in the last [`Phosphor::update`]:
+    /// its dirty areas, plus what was kept lit or is still fading.
+    pub fn changed(&self) -> &[Rect] {
+        &self.changed
+    }
+
+    /// Takes the frame the VM presents, with its dirty areas. Must be called
+    /// on every frame, even without changes, for the fading to go on.
+    pub fn update(&mut self, frame: &Framebuffer, dirty: &[Rect]) {
+        self.changed.clear();
+        if (frame.width(), frame.height()) != (self.width, self.height) {
+            self.width = frame.width();
+            self.height = frame.height();
+            let size = self.width * self.height;
+            self.colors = vec![self.palette[0]; size];
+            self.last_colors = vec![self.palette[0]; size];
+            self.last_bits = vec![0; size];
+            self.last_dirty.clear();
+            self.fading = None;
+            self.changed.push(Rect::new(0, 0, self.width, self.height));
+        } else {
+            self.changed.extend_from_slice(dirty);
+            match self.persistence {
+                Persistence::Off => {}
+                // what was kept lit from the previous frame goes off
+                Persistence::Or => self.changed.extend_from_slice(&self.last_dirty),
+                Persistence::Decay(_) => self.changed.extend(self.fading),
+            }
+        }
+        self.last_dirty.clear();
+        self.last_dirty.extend_from_slice(dirty);
+
+        // the areas may overlap, so the new colours only depend on the last
+        // frame, which is updated in a second pass
+        for rect in &self.changed {
+            for y in rect.y..rect.y + rect.height {
+                for x in rect.x..rect.x + rect.width {
+                    let n = y * self.width + x;
+                    let bits = frame.get(x, y);
+                    self.colors[n] = match self.persistence {
+                        Persistence::Off => self.palette[bits as usize],
+                        Persistence::Or if bits != 0 => self.palette[bits as usize],
+                        Persistence::Or => self.palette[self.last_bits[n] as usize],
+                        Persistence::Decay(_) if bits != 0 => self.palette[bits as usize],
+                        Persistence::Decay(strength) => fade(self.last_colors[n], self.palette[0], strength),
+                    };
+                }
+            }
+        }
+        self.fading = None;
+        for rect in &self.changed {
+            for y in rect.y..rect.y + rect.height {
+                for x in rect.x..rect.x + rect.width {
+                    let n = y * self.width + x;
+                    let bits = frame.get(x, y);
+                    self.last_bits[n] = bits;
+                    self.last_colors[n] = self.colors[n];
+                    if self.colors[n] != self.palette[bits as usize] {
+                        let pixel = Rect::new(x, y, 1, 1);
+                        self.fading = Some(self.fading.map_or(pixel, |fading| fading.union(&pixel)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTE: [u32; 4] = [0x000000, 0xFFFFFF, 0xFF0000, 0x00FF00];
+
+    /// The colour of (0, 0) in every frame, the pixel being lit in the frames listed in `lit`.
+    fn run(persistence: Persistence, lit: &[bool]) -> Vec<u32> {
+        let mut frame = Framebuffer::new();
+        let mut phosphor = Phosphor::new(persistence, PALETTE);
+        phosphor.update(&frame, &[]);
+        let mut on = false;
+        let mut colors = Vec::new();
+        for &lit in lit {
+            let dirty = if lit != on {
+                frame.draw_row(0, 0, 0, 1, 1, false);
+                on = lit;
+                vec![Rect::new(0, 0, 1, 1)]
+            } else {
+                vec![]
+            };
+            phosphor.update(&frame, &dirty);
+            colors.push(phosphor.color(0, 0));
+        }
+        colors
+    }
+
+    #[test]
+    fn off_follows_the_framebuffer() {
+        assert_eq!(run(Persistence::Off, &[true, false, true]), [0xFFFFFF, 0, 0xFFFFFF]);
+    }
+
+    #[test]
+    fn or_keeps_pixels_for_one_more_frame() {
+        assert_eq!(
+            run(Persistence::Or, &[true, false, true, false, false]),
+            [0xFFFFFF, 0xFFFFFF, 0xFFFFFF, 0xFFFFFF, 0]
+        );
+    }
+
+    #[test]
+    fn or_shows_the_current_plane() {
+        let mut frame = Framebuffer::new();
+        let mut phosphor = Phosphor::new(Persistence::Or, PALETTE);
+        phosphor.update(&frame, &[]);
+        frame.draw_row(0, 0, 0, 1, 1, false);
+        phosphor.update(&frame, &[Rect::new(0, 0, 1, 1)]);
+        // moves from the first plane to the second, never both
+        frame.draw_row(0, 0, 0, 1, 1, false);
+        frame.draw_row(1, 0, 0, 1, 1, false);
+        phosphor.update(&frame, &[Rect::new(0, 0, 1, 1)]);
+        assert_eq!(phosphor.color(0, 0), PALETTE[2]);
+        frame.draw_row(1, 0, 0, 1, 1, false);
+        phosphor.update(&frame, &[Rect::new(0, 0, 1, 1)]);
+        assert_eq!(phosphor.color(0, 0), PALETTE[2]);
+        phosphor.update(&frame, &[]);
+        assert_eq!(phosphor.color(0, 0), PALETTE[0]);
+    }
+
+    #[test]
+    fn decay_fades_erased_pixels() {
+        assert_eq!(
+            run(Persistence::Decay(50), &[true, false, false, false, true]),
+            [0xFFFFFF, 0x7F7F7F, 0x3F3F3F, 0x1F1F1F, 0xFFFFFF]
+        );
+        assert_eq!(run(Persistence::Decay(0), &[true, false]), [0xFFFFFF, 0]);
+    }
+
+    #[test]
+    fn decay_goes_on_without_draws() {
+        let mut frame = Framebuffer::new();
+        let mut phosphor = Phosphor::new(Persistence::Decay(10), PALETTE);
+        phosphor.update(&frame, &[]);
+        frame.draw_row(0, 0, 5, 0xFF, 8, false);
+        phosphor.update(&frame, &[Rect::new(0, 5, 8, 1)]);
+        frame.clear(1);
+        phosphor.update(&frame, &[Rect::new(0, 0, 64, 32)]);
+        assert_eq!(phosphor.color(3, 5), 0x191919);
+        phosphor.update(&frame, &[]);
+        assert_eq!(phosphor.changed(), [Rect::new(0, 5, 8, 1)]);
+        assert_eq!(phosphor.color(3, 5), 0x020202);
+        phosphor.update(&frame, &[]);
+        phosphor.update(&frame, &[]);
+        assert_eq!(phosphor.changed(), []);
+    }
+
+    #[test]
+    fn persistence_names() {
+        assert_eq!(Persistence::from_name("or"), Some(Persistence::Or));
+        assert_eq!(Persistence::from_name("decay"), Some(Persistence::Decay(60)));
+        assert_eq!(Persistence::from_name("decay:25"), Some(Persistence::Decay(25)));
+        assert_eq!(Persistence::from_name("decay:100"), None);
+        assert_eq!(Persistence::from_name("blur"), None);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::insts::Instruction;
+
+use crate::quirks::Quirks;
+
+/// The CHIP-8 dialect the VM runs.
+///
+/// Every platform is a superset of the previous one: it decides how much
+/// memory the VM has and which opcodes are accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Platform {
+    /// The original CHIP-8: 4 KiB of memory, 64x32 screen.
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1: adds the 128x64 mode, scrolling and the big font.
+    SuperChip,
+    /// XO-CHIP: 64 KiB of memory, two bitplanes and the long I load.
+    XoChip,
+}
+
+impl Platform {
+    /// Size of the address space, in bytes.
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 4096,
+            Platform::XoChip => 65536,
+        }
+    }
+
+    /// The quirks ROMs written for this platform usually expect.
+    pub fn default_quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::COSMAC_VIP,
+            Platform::SuperChip => Quirks::SUPER_CHIP,
+            Platform::XoChip => Quirks::OCTO,
+        }
+    }
+
+    /// Whether the platform knows this instruction.
+    pub fn supports(&self, instruction: &Instruction) -> bool {
+        let superchip = matches!(
+            instruction,
+            Instruction::ScrollDown(_)
+                | Instruction::ScrollRight
+                | Instruction::ScrollLeft
+                | Instruction::Exit
+                | Instruction::LowRes
+                | Instruction::HighRes
+                | Instruction::BigSpriteDigit(_)
+                | Instruction::StoreFlags(_)
+                | Instruction::ReadFlags(_)
+        );
+        let xochip = matches!(
+            instruction,
+            Instruction::ScrollUp(_)
+                | Instruction::SaveRange(_, _)
+                | Instruction::LoadRange(_, _)
+                | Instruction::LongI
+                | Instruction::SelectPlane(_)
+                | Instruction::LoadAudio
+                | Instruction::SetPitch(_)
+        );
+        match self {
+            Platform::Chip8 => !superchip && !xochip,
+            Platform::SuperChip => !xochip,
+            Platform::XoChip => true,
+        }
+    }
+
+    /// Looks a platform up by name: `chip8`, `schip` or `xochip`.
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name {
+            "chip8" | "chip-8" => Some(Platform::Chip8),
+            "schip" | "superchip" | "super-chip" => Some(Platform::SuperChip),
+            "xochip" | "xo-chip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    /// Guesses the platform from the usual ROM extensions, CHIP-8 by default.
+    pub fn from_path(path: &str) -> Platform {
+        if path.ends_with(".xo8") {
+            Platform::XoChip
+        } else if path.ends_with(".sc8") {
+            Platform::SuperChip
+        } else {
+            Platform::Chip8
+        }
+    }
+}
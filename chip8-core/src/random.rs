@@ -0,0 +1,138 @@
+use alloc::boxed::Box;
+
+use crate::vm::RandomHandler;
+
+/// Small xorshift32 generator, the same seed always gives the same numbers.
+#[derive(Debug, Clone)]
+pub struct XorShiftRandom {
+    state: u32,
+}
+
+impl XorShiftRandom {
+    /// A seed of 0 would only produce zeros, it is replaced by a fixed constant.
+    pub fn new(seed: u32) -> Self {
+        XorShiftRandom {
+            state: if seed == 0 { 0x2545_F491 } else { seed },
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+}
+
+impl RandomHandler for XorShiftRandom {
+    fn random(&mut self) -> u8 {
+        // the high bits of xorshift are the best mixed ones
+        (self.next_u32() >> 24) as u8
+    }
+}
+
+/// Random numbers the way the COSMAC VIP interpreter makes them.
+///
+/// The VIP walks a pointer through page 0x01 of its own code and adds the
+/// byte found there to the previous result. Give it that page from a dump
+/// of the interpreter to reproduce recordings of the real machine.
+#[derive(Debug, Clone)]
+pub struct VipRandom {
+    page: [u8; 256],
+    pointer: u8,
+    last: u8,
+}
+
+impl VipRandom {
+    pub fn new(page: [u8; 256]) -> Self {
+        VipRandom {
+            page,
+            pointer: 0,
+            last: 0,
+        }
+    }
+
+    /// Takes page 0x01 from a dump of the interpreter: its 512 bytes (or more,
+    /// from 0x000), or only the 256 bytes of the page. None for other sizes.
+    pub fn from_dump(dump: &[u8]) -> Option<Self> {
+        let page = match dump.len() {
+            256 => dump,
+            len if len >= 512 => &dump[0x100..0x200],
+            _ => return None,
+        };
+        page.try_into().ok().map(VipRandom::new)
+    }
+}
+
+impl RandomHandler for VipRandom {
+    fn random(&mut self) -> u8 {
+        self.pointer = self.pointer.wrapping_add(1);
+        self.last = self.last.wrapping_add(self.page[self.pointer as usize]);
+        self.last
+    }
+}
+
+/// One of the generators above, for frontends that let the user pick it.
+#[derive(Debug, Clone)]
+pub enum AnyRandom {
+    XorShift(XorShiftRandom),
+    Vip(Box<VipRandom>),
+}
+
+impl RandomHandler for AnyRandom {
+    fn random(&mut self) -> u8 {
+        match self {
+            AnyRandom::XorShift(rng) => rng.random(),
+            AnyRandom::Vip(rng) => rng.random(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec::Vec;
+
+    #[test]
+    fn vip_adds_up_its_page() {
+        let mut dump = [0u8; 512];
+        dump[0x101] = 0x10;
+        dump[0x102] = 0xF5;
+        let mut rng = VipRandom::from_dump(&dump).unwrap();
+        assert_eq!([rng.random(), rng.random(), rng.random()], [0x10, 0x05, 0x05]);
+        assert!(VipRandom::from_dump(&dump[..256]).is_some());
+        assert!(VipRandom::from_dump(&dump[..300]).is_none());
+    }
+
+    fn numbers(rng: &mut impl RandomHandler, count: usize) -> Vec<u8> {
+        (0..count).map(|_| rng.random()).collect()
+    }
+
+    #[test]
+    fn xorshift_repeats_for_the_same_seed() {
+        let first = numbers(&mut XorShiftRandom::new(42), 32);
+        assert_eq!(numbers(&mut XorShiftRandom::new(42), 32), first);
+        assert_ne!(numbers(&mut XorShiftRandom::new(43), 32), first);
+    }
+
+    #[test]
+    fn xorshift_seed_zero_is_replaced() {
+        let mut rng = XorShiftRandom::new(0);
+        assert_ne!(rng.next_u32(), 0);
+        assert!(numbers(&mut rng, 32).iter().any(|n| *n != 0));
+        assert_eq!(numbers(&mut XorShiftRandom::new(0), 32), numbers(&mut XorShiftRandom::new(0x2545_F491), 32));
+    }
+
+    #[test]
+    fn any_random_forwards_to_its_generator() {
+        let mut page = [0u8; 256];
+        page[1] = 3;
+        let mut vip = AnyRandom::Vip(Box::new(VipRandom::new(page)));
+        assert_eq!(numbers(&mut vip, 3), [3, 3, 3]);
+        let mut xorshift = AnyRandom::XorShift(XorShiftRandom::new(7));
+        assert_eq!(numbers(&mut xorshift, 16), numbers(&mut XorShiftRandom::new(7), 16));
+    }
+}
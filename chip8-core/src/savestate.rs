@@ -0,0 +1,132 @@
+use core::fmt;
+
+use alloc::vec::Vec;
+
+use crate::platform::Platform;
+
+/// First bytes of every save state.
+pub const STATE_MAGIC: [u8; 4] = *b"C8ST";
+
+/// Bumped every time the layout of a save state changes.
+pub const STATE_VERSION: u8 = 2;
+
+/// Why a save state could not be loaded. The VM is left untouched in every case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// The data doesn't start with [`STATE_MAGIC`].
+    BadMagic,
+    /// The state was written by another version of the emulator.
+    UnsupportedVersion(u8),
+    /// The state was made on another platform.
+    WrongPlatform { expected: Platform, found: Platform },
+    /// The state was made for another ROM.
+    WrongRom { expected: u32, found: u32 },
+    /// The data ends too early or holds impossible values.
+    Corrupted,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "save state version {} is not supported (expected {})", version, STATE_VERSION)
+            }
+            StateError::WrongPlatform { expected, found } => {
+                write!(f, "save state is for {:?}, the VM runs {:?}", found, expected)
+            }
+            StateError::WrongRom { expected, found } => {
+                write!(f, "save state is for ROM {:08X}, the loaded ROM is {:08X}", found, expected)
+            }
+            StateError::Corrupted => write!(f, "save state is corrupted"),
+        }
+    }
+}
+
+/// FNV-1a hash of a ROM, stored in the header of save states.
+pub fn rom_hash(rom: &[u8]) -> u32 {
+    rom.iter().fold(0x811C_9DC5, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x0100_0193))
+}
+
+pub(crate) fn platform_id(platform: Platform) -> u8 {
+    match platform {
+        Platform::Chip8 => 0,
+        Platform::SuperChip => 1,
+        Platform::XoChip => 2,
+    }
+}
+
+pub(crate) fn platform_from_id(id: u8) -> Option<Platform> {
+    match id {
+        0 => Some(Platform::Chip8),
+        1 => Some(Platform::SuperChip),
+        2 => Some(Platform::XoChip),
+        _ => None,
+    }
+}
+
+/// Little-endian encoder for save states.
+pub(crate) struct StateWriter {
+    pub data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.data.extend_from_slice(value);
+    }
+}
+
+/// Little-endian decoder for save states, every read fails with [`StateError::Corrupted`]
+/// past the end of the data.
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Corrupted);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
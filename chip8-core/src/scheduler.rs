@@ -0,0 +1,259 @@
+use crate::error::VmError;
+
+use crate::vm::{DisplayHandler, KeyboardHandler, RandomHandler, SoundHandler, StepOutcome, VM};
+
+/// Clock used to pace the VM, provided by the frontend.
+pub trait TimeSource {
+    /// Microseconds elapsed since an arbitrary origin.
+    fn now_us(&mut self) -> u64;
+    /// Gives the CPU away for about `us` microseconds.
+    fn sleep_us(&mut self, us: u64);
+}
+
+/// If we are late by more than this many frames we stop trying to catch up.
+const MAX_LAG_FRAMES: u64 = 5;
+
+/// Runs the VM at a fixed number of instructions per second, split into
+/// frames of `1/fps` seconds. The timers are decreased once per frame.
+pub struct Scheduler {
+    ips: u32,
+    fps: u32,
+    budget: u32, // leftover of ips / fps, carried to the next frame
+    origin: Option<u64>,
+    frame: u64, // frames run since origin
+}
+
+impl Scheduler {
+    pub fn new(ips: u32, fps: u32) -> Self {
+        Scheduler {
+            ips,
+            fps: fps.max(1),
+            budget: 0,
+            origin: None,
+            frame: 0,
+        }
+    }
+
+    pub fn ips(&self) -> u32 {
+        self.ips
+    }
+
+    /// Changes the speed of the VM, effective from the next frame.
+    pub fn set_ips(&mut self, ips: u32) {
+        self.ips = ips;
+        self.budget = 0;
+    }
+
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    /// Changes the frame rate, effective from the next frame.
+    pub fn set_fps(&mut self, fps: u32) {
+        self.fps = fps.max(1);
+        self.budget = 0;
+        self.origin = None;
+    }
+
+    fn frame_us(&self, frames: u64) -> u64 {
+        frames * 1_000_000 / self.fps as u64
+    }
+
+    /// Runs the instructions of one frame, decreases the timers, then sleeps
+    /// until the frame is over.
+    ///
+    /// The frame ends early when the VM waits for a key or for the vertical
+    /// blank. Returns the outcome of the last instruction executed.
+    pub fn run_frame<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(
+        &mut self,
+        vm: &mut VM<T, T2, T3, T4>,
+        time: &mut impl TimeSource,
+    ) -> Result<StepOutcome, VmError> {
+        self.run_frame_until(vm, time, |_| false).map(|outcome| outcome.unwrap_or(StepOutcome::Continue))
+    }
+
+    /// Same as [`Scheduler::run_frame`], but asks `stop` before every instruction.
+    /// When it says so, returns `Ok(None)` at once without finishing the frame.
+    pub fn run_frame_until<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(
+        &mut self,
+        vm: &mut VM<T, T2, T3, T4>,
+        time: &mut impl TimeSource,
+        mut stop: impl FnMut(&VM<T, T2, T3, T4>) -> bool,
+    ) -> Result<Option<StepOutcome>, VmError> {
+        let origin = match self.origin {
+            Some(origin) => origin,
+            None => {
+                let now = time.now_us();
+                self.origin = Some(now);
+                self.frame = 0;
+                now
+            }
+        };
+
+        self.budget += self.ips;
+        let count = self.budget / self.fps;
+        self.budget %= self.fps;
+
+        let mut outcome = StepOutcome::Continue;
+        for done in 0..count {
+            if stop(vm) {
+                // the rest of the frame runs once we are resumed
+                self.budget += (count - done) * self.fps;
+                self.origin = None;
+                return Ok(None);
+            }
+            outcome = vm.process()?;
+            if outcome != StepOutcome::Continue {
+                break;
+            }
+        }
+        if outcome == StepOutcome::Exit {
+            return Ok(Some(outcome));
+        }
+
+        vm.decrease_timer();
+
+        self.frame += 1;
+        let deadline = origin + self.frame_us(self.frame);
+        let now = time.now_us();
+        if now < deadline {
+            time.sleep_us(deadline - now);
+        } else if now - deadline > self.frame_us(MAX_LAG_FRAMES) {
+            // the machine was suspended or is too slow, start counting again
+            self.origin = None;
+        }
+
+        Ok(Some(outcome))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec::Vec;
+
+    use crate::platform::Platform;
+    use crate::quirks::Quirks;
+    use crate::vm::tests::{test_vm, TestVm};
+
+    /// Time only moves when the scheduler sleeps, or when a test says so.
+    #[derive(Default)]
+    struct FakeClock {
+        now: u64,
+        sleeps: Vec<u64>,
+    }
+
+    impl TimeSource for FakeClock {
+        fn now_us(&mut self) -> u64 {
+            self.now
+        }
+
+        fn sleep_us(&mut self, us: u64) {
+            self.sleeps.push(us);
+            self.now += us;
+        }
+    }
+
+    /// `v0 += 1` over and over: V0 counts the instructions executed.
+    fn counter() -> TestVm {
+        let rom: Vec<u8> = [0x70, 0x01].repeat(1000);
+        test_vm(&rom, Platform::Chip8, Quirks::CHIP_48)
+    }
+
+    /// Runs `frames` frames, returns V0 after each one.
+    fn frames(scheduler: &mut Scheduler, vm: &mut TestVm, clock: &mut FakeClock, frames: usize) -> Vec<u8> {
+        (0..frames)
+            .map(|_| {
+                scheduler.run_frame(vm, clock).unwrap();
+                vm.registers()[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frames_share_the_instructions() {
+        let mut vm = counter();
+        let mut clock = FakeClock::default();
+        // 10.83 instructions per frame, the fractions add up
+        let mut scheduler = Scheduler::new(650, 60);
+        assert_eq!(frames(&mut scheduler, &mut vm, &mut clock, 3), [10, 21, 32]);
+    }
+
+    #[test]
+    fn timers_tick_once_per_frame() {
+        // delay := 0xFF, then loop
+        let mut vm = test_vm(&[0x60, 0xFF, 0xF0, 0x15, 0x12, 0x04], Platform::Chip8, Quirks::CHIP_48);
+        let mut clock = FakeClock::default();
+        let mut scheduler = Scheduler::new(600, 60);
+        for _ in 0..60 {
+            scheduler.run_frame(&mut vm, &mut clock).unwrap();
+        }
+        assert_eq!(vm.delay_timer(), 0xFF - 60);
+        assert_eq!(clock.now, 1_000_000);
+        assert!(clock.sleeps.iter().all(|us| (16_666..=16_667).contains(us)));
+    }
+
+    #[test]
+    fn waiting_for_the_vblank_ends_the_frame() {
+        // v1 += 1, sprite v0 v0 1, jump back
+        let rom = [0x71, 0x01, 0xD0, 0x01, 0x12, 0x00];
+        let mut vm = test_vm(&rom, Platform::Chip8, Quirks::COSMAC_VIP);
+        let mut clock = FakeClock::default();
+        let mut scheduler = Scheduler::new(6000, 60);
+        let outcome = scheduler.run_frame(&mut vm, &mut clock).unwrap();
+        assert_eq!((outcome, vm.registers()[1]), (StepOutcome::WaitingForVblank, 1));
+        // one draw per frame
+        for _ in 0..4 {
+            scheduler.run_frame(&mut vm, &mut clock).unwrap();
+        }
+        assert_eq!(vm.registers()[1], 5);
+        assert_eq!(clock.now, scheduler.frame_us(5));
+    }
+
+    #[test]
+    fn long_stalls_are_not_caught_up() {
+        let mut vm = counter();
+        let mut clock = FakeClock::default();
+        let mut scheduler = Scheduler::new(600, 60);
+        scheduler.run_frame(&mut vm, &mut clock).unwrap();
+        // the machine was suspended for a second
+        clock.now += 1_000_000;
+        scheduler.run_frame(&mut vm, &mut clock).unwrap();
+        assert_eq!(clock.sleeps, [16_666]);
+        // the next frame starts counting again instead of rushing 59 frames
+        scheduler.run_frame(&mut vm, &mut clock).unwrap();
+        assert_eq!(clock.sleeps, [16_666, 16_666]);
+        assert_eq!(vm.registers()[0], 30);
+    }
+
+    #[test]
+    fn short_delays_are_caught_up() {
+        let mut vm = counter();
+        let mut clock = FakeClock::default();
+        let mut scheduler = Scheduler::new(600, 60);
+        scheduler.run_frame(&mut vm, &mut clock).unwrap();
+        clock.now += 20_000;
+        // the late frame doesn't sleep, the next one sleeps less
+        scheduler.run_frame(&mut vm, &mut clock).unwrap();
+        scheduler.run_frame(&mut vm, &mut clock).unwrap();
+        assert_eq!(clock.sleeps, [16_666, 13_334]);
+        assert_eq!(clock.now, 50_000);
+    }
+
+    #[test]
+    fn speed_changes_at_runtime() {
+        let mut vm = counter();
+        let mut clock = FakeClock::default();
+        let mut scheduler = Scheduler::new(600, 60);
+        assert_eq!(frames(&mut scheduler, &mut vm, &mut clock, 1), [10]);
+        scheduler.set_ips(1200);
+        assert_eq!(frames(&mut scheduler, &mut vm, &mut clock, 2), [30, 50]);
+        scheduler.set_fps(30);
+        clock.sleeps.clear();
+        assert_eq!(frames(&mut scheduler, &mut vm, &mut clock, 2), [90, 130]);
+        assert_eq!(clock.sleeps, [33_333, 33_333]);
+        scheduler.set_fps(0);
+        assert_eq!(scheduler.fps(), 1);
+    }
+}
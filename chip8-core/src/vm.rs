@@ -0,0 +1,1385 @@
+use crate::insts::Instruction;
+
+use crate::error::VmError;
+
+use crate::quirks::Quirks;
+
+use crate::platform::Platform;
+
+use crate::display::{Framebuffer, Rect, MAX_HEIGHT, MAX_WIDTH};
+
+use crate::audio::{Audio, DEFAULT_PITCH};
+
+use crate::savestate::{self, StateError, StateReader, StateWriter, STATE_MAGIC, STATE_VERSION};
+
+use alloc::boxed::Box;
+use alloc::{vec, vec::Vec};
+
+pub trait DisplayHandler {
+    /// Called on every vertical blank, `dirty` lists the areas changed since
+    /// the last call and is empty when nothing changed. After a switch between
+    /// 64x32 and 128x64 it holds the whole screen.
+    fn present(&mut self, frame: &Framebuffer, dirty: &[Rect]);
+}
+
+pub trait KeyboardHandler {
+    fn is_pressed(&mut self, key: u8) -> bool;
+    /// The last key that was pressed then released, forgotten once returned.
+    fn take_released(&mut self) -> Option<u8>;
+}
+
+pub trait RandomHandler {
+    fn random(&mut self) -> u8;
+}
+
+/// What the buzzer plays while the sound timer isn't zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tone {
+    /// In Hz.
+    pub frequency: u32,
+    /// From 0 (muted) to 100.
+    pub volume: u8,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Tone { frequency: 440, volume: 50 }
+    }
+}
+
+pub trait SoundHandler {
+    /// The sound timer went from 0 to a positive value.
+    fn start(&mut self, tone: Tone);
+    /// The sound timer is back to 0.
+    fn stop(&mut self);
+    /// Called on every vertical blank with what was played during the frame,
+    /// for handlers that make samples with [`crate::audio::Synth`].
+    fn frame(&mut self, _audio: &Audio) {}
+}
+
+/// Logging hook, told about every instruction before it is executed.
+pub trait Tracer {
+    fn trace(&mut self, pc: u16, instruction: &Instruction);
+}
+
+static FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// SUPER-CHIP 10-row digits, drawn by `BigSpriteDigit`.
+static BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// The big font is stored right after the small one.
+const BIG_FONT_ADDR: usize = 0x50;
+
+/// Maximum depth of nested subroutine calls.
+pub const STACK_SIZE: usize = 16;
+
+/// What happened during a successful call to [`VM::process`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The instruction was executed and the program counter moved on.
+    Continue,
+    /// The VM is blocked on `WaitKey` until a key is pressed and released,
+    /// the same instruction runs again next time. Timers keep running meanwhile.
+    WaitingForKey,
+    /// `Draw` waits for the next vertical blank (see [`Quirks::display_wait`]).
+    WaitingForVblank,
+    /// The ROM executed `Exit` (00FD), the VM stays on that instruction.
+    Exit,
+}
+
+pub struct VM<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler> {
+    pub memory: Vec<u8>, // 4096 bytes, 65536 on XO-CHIP
+    registers: [u8; 16], // 8-bit data registers
+    stack: Vec<u16>,
+    i: u16,
+    programcounter: usize,
+    delaytimer: u8,
+    soundtimer: u8,
+    pub displayhandler: T,
+    pub keyboardhandler: T2,
+    pub rng: T3,
+    pub soundhandler: T4,
+    /// Played by `soundhandler`, takes effect on the next beep.
+    pub tone: Tone,
+    framebuffer: Framebuffer,
+    planes: u8, // planes selected for drawing, XO-CHIP only
+    audio_pattern: Option<[u8; 16]>, // XO-CHIP only
+    pitch: u8,
+    waiting_key: bool, // inside a WaitKey
+    rpl: [u8; 16], // user flags, 8 on SUPER-CHIP and 16 on XO-CHIP
+    pub quirks: Quirks,
+    platform: Platform,
+    vblank: bool,
+    rom_hash: u32,
+    pub tracer: Option<Box<dyn Tracer>>,
+}
+
+impl<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler> VM<T, T2, T3, T4> {
+    pub fn new(
+        displayhandler: T,
+        keyboardhandler: T2,
+        randomhandler: T3,
+        soundhandler: T4,
+        platform: Platform,
+        quirks: Quirks,
+    ) -> Self {
+        let mut memory = vec![0; platform.memory_size()];
+        memory[..FONT.len()].copy_from_slice(&FONT);
+        memory[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
+        VM {
+            memory,
+            registers: [0; 16],
+            stack: vec![],
+            i: 0,
+            programcounter: 0x200, // start of programs
+            delaytimer: 0,
+            soundtimer: 0,
+            displayhandler,
+            keyboardhandler,
+            rng: randomhandler,
+            soundhandler,
+            tone: Tone::default(),
+            framebuffer: Framebuffer::new(),
+            planes: 1,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            waiting_key: false,
+            rpl: [0; 16],
+            quirks,
+            platform,
+            vblank: false,
+            rom_hash: savestate::rom_hash(&[]),
+            tracer: None,
+        }
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Address of the next instruction.
+    pub fn pc(&self) -> u16 {
+        self.programcounter as u16
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    /// The I register.
+    pub fn index(&self) -> u16 {
+        self.i
+    }
+
+    /// Return addresses, the innermost call last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delaytimer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.soundtimer
+    }
+
+    /// What the buzzer plays right now.
+    pub fn audio(&self) -> Audio {
+        Audio {
+            playing: self.soundtimer > 0,
+            tone: self.tone,
+            pattern: self.audio_pattern,
+            pitch: self.pitch,
+        }
+    }
+
+    /// The opcode stored at `addr`, None past the end of the memory.
+    pub fn opcode_at(&self, addr: usize) -> Option<u16> {
+        let high = *self.memory.get(addr)?;
+        let low = *self.memory.get(addr + 1)?;
+        Some(((high as u16) << 8) | low as u16)
+    }
+
+    /// Width of the screen in the current resolution.
+    pub fn width(&self) -> usize {
+        self.framebuffer.width()
+    }
+
+    /// Height of the screen in the current resolution.
+    pub fn height(&self) -> usize {
+        self.framebuffer.height()
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// Hands the areas changed since the last call to the display handler.
+    /// Called on every vertical blank, frontends only need it to show the
+    /// screen while the VM doesn't run, in the debugger for instance.
+    pub fn present(&mut self) {
+        let dirty = self.framebuffer.take_dirty();
+        self.displayhandler.present(&self.framebuffer, &dirty);
+        self.framebuffer.recycle_dirty(dirty);
+    }
+
+    /// Length of the instruction at `addr`, F000 NNNN takes four bytes.
+    pub fn instruction_len(&self, addr: usize) -> usize {
+        let long = self.platform == Platform::XoChip
+            && self.memory.get(addr) == Some(&0xF0)
+            && self.memory.get(addr + 1) == Some(&0x00);
+        if long { 4 } else { 2 }
+    }
+
+    /// Last register FX75 and FX85 reach: SUPER-CHIP has 8 user flags, XO-CHIP 16.
+    fn flags_up_to(&self, x: u8) -> usize {
+        match self.platform {
+            Platform::SuperChip => x.min(7) as usize,
+            _ => x as usize,
+        }
+    }
+
+    pub fn check_key(&mut self, key: u8) -> bool {
+        self.keyboardhandler.is_pressed(key)
+    }
+
+    /// Called at 60 Hz, on every vertical blank. Also presents the screen.
+    pub fn decrease_timer(&mut self) {
+        self.vblank = true;
+        self.present();
+        self.soundhandler.frame(&self.audio());
+        if self.delaytimer > 0 {
+            self.delaytimer -= 1;
+        }
+        if self.soundtimer > 0 {
+            self.set_sound_timer(self.soundtimer - 1);
+        }
+    }
+
+    /// Tells the sound handler when the timer crosses zero.
+    fn set_sound_timer(&mut self, value: u8) {
+        let playing = self.soundtimer > 0;
+        self.soundtimer = value;
+        match (playing, value > 0) {
+            (false, true) => self.soundhandler.start(self.tone),
+            (true, false) => self.soundhandler.stop(),
+            _ => {}
+        }
+    }
+
+    pub fn setmemory(&mut self, content: Vec<u8>) {
+        self.rom_hash = savestate::rom_hash(&content);
+
+        let max_memory_size = self.memory.len() - 0x200; // Reserve the first 512 bytes for system area and font set
+    
+        let memory_size = core::cmp::min(content.len(), max_memory_size);
+    
+        self.memory[0x200..0x200 + memory_size].copy_from_slice(&content[..memory_size]);
+    }
+
+    /// Hash of the loaded ROM, as stored in save states.
+    pub fn rom_hash(&self) -> u32 {
+        self.rom_hash
+    }
+
+    /// Captures the whole machine: memory, registers, stack, timers and screen.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.bytes(&STATE_MAGIC);
+        w.u8(STATE_VERSION);
+        w.u8(savestate::platform_id(self.platform));
+        w.u32(self.rom_hash);
+
+        w.u16(self.programcounter as u16);
+        w.u16(self.i);
+        w.u8(self.delaytimer);
+        w.u8(self.soundtimer);
+        w.bytes(&self.registers);
+        w.u8(self.stack.len() as u8);
+        for addr in &self.stack {
+            w.u16(*addr);
+        }
+        w.bytes(&self.rpl);
+        w.u8(self.framebuffer.hires() as u8);
+        w.u8(self.planes);
+        w.u8(self.audio_pattern.is_some() as u8);
+        w.bytes(&self.audio_pattern.unwrap_or_default());
+        w.u8(self.pitch);
+        w.u8(self.waiting_key as u8);
+        w.u8(self.vblank as u8);
+
+        // four 2-bit pixels per byte
+        for x in 0..MAX_WIDTH {
+            for y in (0..MAX_HEIGHT).step_by(4) {
+                w.u8((0..4).fold(0, |acc, n| acc | self.framebuffer.get(x, y + n) << (2 * n)));
+            }
+        }
+        w.u32(self.memory.len() as u32);
+        w.bytes(&self.memory);
+        w.data
+    }
+
+    /// Restores a state made by [`VM::save_state`] with the same ROM and platform.
+    /// On error the VM is left untouched.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(data);
+        if r.bytes(4).map_err(|_| StateError::BadMagic)? != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = r.u8()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let platform = savestate::platform_from_id(r.u8()?).ok_or(StateError::Corrupted)?;
+        if platform != self.platform {
+            return Err(StateError::WrongPlatform { expected: self.platform, found: platform });
+        }
+        let hash = r.u32()?;
+        if hash != self.rom_hash {
+            return Err(StateError::WrongRom { expected: self.rom_hash, found: hash });
+        }
+
+        let programcounter = r.u16()? as usize;
+        let i = r.u16()?;
+        let delaytimer = r.u8()?;
+        let soundtimer = r.u8()?;
+        let mut registers = [0; 16];
+        registers.copy_from_slice(r.bytes(16)?);
+        let depth = r.u8()? as usize;
+        if depth > STACK_SIZE {
+            return Err(StateError::Corrupted);
+        }
+        let mut stack = Vec::with_capacity(depth);
+        for _ in 0..depth {
+            stack.push(r.u16()?);
+        }
+        let mut rpl = [0; 16];
+        rpl.copy_from_slice(r.bytes(16)?);
+        let hires = r.u8()? != 0;
+        let planes = r.u8()? & 0x3;
+        let has_pattern = r.u8()? != 0;
+        let mut pattern = [0; 16];
+        pattern.copy_from_slice(r.bytes(16)?);
+        let audio_pattern = if has_pattern { Some(pattern) } else { None };
+        let pitch = r.u8()?;
+        let waiting_key = r.u8()? != 0;
+        let vblank = r.u8()? != 0;
+
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_hires(hires);
+        for x in 0..MAX_WIDTH {
+            for y in (0..MAX_HEIGHT).step_by(4) {
+                let packed = r.u8()?;
+                for n in 0..4 {
+                    framebuffer.set(x, y + n, packed >> (2 * n));
+                }
+            }
+        }
+        let memory_len = r.u32()? as usize;
+        if memory_len != self.memory.len() {
+            return Err(StateError::Corrupted);
+        }
+        let memory = r.bytes(memory_len)?;
+        if !r.is_empty() {
+            return Err(StateError::Corrupted);
+        }
+
+        self.programcounter = programcounter;
+        self.i = i;
+        self.delaytimer = delaytimer;
+        self.set_sound_timer(soundtimer);
+        self.registers = registers;
+        self.stack = stack;
+        self.rpl = rpl;
+        self.planes = planes;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        self.waiting_key = waiting_key;
+        self.vblank = vblank;
+        self.framebuffer = framebuffer;
+        self.framebuffer.mark_all();
+        self.memory.copy_from_slice(memory);
+        Ok(())
+    }
+
+    fn random(&mut self) -> u8 {
+        self.rng.random()
+    }
+
+    pub fn process(&mut self) -> Result<StepOutcome, VmError> {
+        let pc = self.programcounter;
+        if pc + 1 >= self.memory.len() {
+            return Err(VmError::InvalidPc { pc: pc as u16 });
+        }
+
+        let instruction1: u8 = self.memory[pc];
+        let instruction2: u8 = self.memory[pc + 1];
+        let opcode = ((instruction1 as u16) << 8) | instruction2 as u16;
+
+        let instruction = Instruction::new(opcode);
+        if !self.platform.supports(&instruction) {
+            return Err(VmError::UnknownOpcode { pc: pc as u16, opcode });
+        }
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.trace(pc as u16, &instruction);
+        }
+
+        let oob = |address: usize| VmError::MemoryOutOfBounds { pc: pc as u16, opcode, address };
+
+        let mut next = pc + 2;
+        let mut outcome = StepOutcome::Continue;
+
+        match instruction {
+            Instruction::ClearScreen => {
+                self.framebuffer.clear(self.planes);
+            },
+
+            Instruction::Jump(val) => {
+                next = val as usize;
+            }
+
+            Instruction::SetRegister(reg, val) => {
+                self.registers[reg as usize] = val;
+            }
+
+            Instruction::AddRegister(reg, val) => {
+                self.registers[reg as usize] = (val as usize + self.registers[reg as usize] as usize) as u8;
+            }
+
+            Instruction::SetI(val) => {
+                self.i = val;
+            }
+
+            Instruction::Draw(reg1, reg2, size) => {
+                if self.quirks.display_wait && !self.vblank {
+                    return Ok(StepOutcome::WaitingForVblank);
+                }
+                self.vblank = false;
+                let (width, height) = (self.width(), self.height());
+                // the starting position always wraps, the edges clip or wrap depending on the quirk
+                let x = self.registers[reg1 as usize] as usize % width;
+                let y = self.registers[reg2 as usize] as usize % height;
+                // DXY0 draws a 16x16 sprite, two bytes per row
+                let (rows, row_bytes) = if size == 0 && self.platform != Platform::Chip8 {
+                    (16, 2)
+                } else {
+                    (size as usize, 1)
+                };
+                let sprite_width = 8 * row_bytes;
+                let wrap = !self.quirks.clip_sprites;
+                // every selected plane reads its own copy of the sprite, one after the other
+                let sprite_len = rows * row_bytes;
+                let mut collision = false;
+                for (n, plane) in (0..2).filter(|plane| self.planes & (1 << plane) != 0).enumerate() {
+                    let sprite = self.i as usize + n * sprite_len;
+                    for row in 0..rows {
+                        let mut py = y + row;
+                        if py >= height {
+                            if !wrap {
+                                break;
+                            }
+                            py %= height;
+                        }
+                        // a sprite past the end of the memory continues at its start
+                        let bits = (0..row_bytes).fold(0u16, |acc, byte| {
+                            let addr = (sprite + row * row_bytes + byte) % self.memory.len();
+                            acc << 8 | self.memory[addr] as u16
+                        });
+                        collision |= self.framebuffer.draw_row(plane, x, py, bits, sprite_width, wrap);
+                    }
+                }
+                for (left, columns) in spans(x, sprite_width, width, !wrap) {
+                    for (top, lines) in spans(y, rows, height, !wrap) {
+                        self.framebuffer.mark(Rect::new(left, top, columns, lines));
+                    }
+                }
+                self.registers[15] = collision as u8;
+            }
+
+            Instruction::Call(addr) => {
+                if self.stack.len() >= STACK_SIZE {
+                    return Err(VmError::StackOverflow { pc: pc as u16, opcode });
+                }
+                self.stack.push(next as u16);
+                next = addr as usize;
+            }
+
+            Instruction::Ret => {
+                let val = self.stack.pop();
+                match val {
+                    Some(value) => {
+                        next = value as usize;
+                    }
+                    None => {
+                        return Err(VmError::StackUnderflow { pc: pc as u16, opcode });
+                    }
+                }
+            }
+
+            Instruction::SkipNextInstruction(reg, val) => {
+                let val_reg = self.registers[reg as usize];
+                if val_reg == val {
+                    next += self.instruction_len(next);
+                }
+            }
+
+            Instruction::NSkipNextInstruction(reg, val) => {
+                let val_reg = self.registers[reg as usize];
+                if val_reg != val {
+                    next += self.instruction_len(next);
+                }
+            }
+
+            Instruction::R2SkipNextInstruction(reg1, reg2) => {
+                let val_reg1 = self.registers[reg1 as usize] as u16;
+                let val_reg2 = self.registers[reg2 as usize] as u16;
+                if val_reg1 == val_reg2 {
+                    next += self.instruction_len(next);
+                }
+            }
+
+            Instruction::NR2SkipNextInstruction(reg1, reg2) => {
+                let val_reg1 = self.registers[reg1 as usize] as u16;
+                let val_reg2 = self.registers[reg2 as usize] as u16;
+                if val_reg1 != val_reg2 {
+                    next += self.instruction_len(next);
+                }
+            }
+
+            Instruction::ERROR(nb) => {
+                return Err(VmError::UnknownOpcode { pc: pc as u16, opcode: nb });
+            }
+
+            Instruction::STORE(a, b) => {
+                self.registers[a as usize] = self.registers[b as usize];
+            }
+
+            Instruction::OR(a, b) => {
+                self.registers[a as usize] |= self.registers[b as usize];
+                if self.quirks.logic_resets_vf {
+                    self.registers[15] = 0;
+                }
+            }
+
+            Instruction::AND(a, b) => {
+                self.registers[a as usize] &= self.registers[b as usize];
+                if self.quirks.logic_resets_vf {
+                    self.registers[15] = 0;
+                }
+            }
+
+            Instruction::XOR(a, b) => {
+                self.registers[a as usize] ^= self.registers[b as usize];
+                if self.quirks.logic_resets_vf {
+                    self.registers[15] = 0;
+                }
+            }
+
+            // the flag is written after the result, so it wins when X is F
+            Instruction::ADD(a, b) => {
+                let (sum, carry) = self.registers[a as usize].overflowing_add(self.registers[b as usize]);
+                self.registers[a as usize] = sum;
+                self.registers[15] = carry as u8;
+            }
+
+            Instruction::SUB(a, b) => {
+                let reg1 = self.registers[a as usize];
+                let reg2 = self.registers[b as usize];
+                self.registers[a as usize] = reg1.wrapping_sub(reg2);
+                self.registers[15] = (reg1 >= reg2) as u8;
+            }
+
+            Instruction::SHR(a, b) => {
+                let reg1 = if self.quirks.shift_uses_vy { self.registers[b as usize] } else { self.registers[a as usize] };
+                self.registers[a as usize] = reg1 >> 1;
+                self.registers[15] = reg1 & 0x1;
+            }
+
+            Instruction::SUBN(a, b) => {
+                let reg1 = self.registers[a as usize];
+                let reg2 = self.registers[b as usize];
+                self.registers[a as usize] = reg2.wrapping_sub(reg1);
+                self.registers[15] = (reg2 >= reg1) as u8;
+            }
+
+            Instruction::SHL(a, b) => {
+                let reg1 = if self.quirks.shift_uses_vy { self.registers[b as usize] } else { self.registers[a as usize] };
+                self.registers[a as usize] = reg1 << 1;
+                self.registers[15] = reg1 >> 7;
+            }
+
+            Instruction::AddI(reg) => {
+                self.i = self.i.wrapping_add(self.registers[reg as usize] as u16);
+            }
+
+            Instruction::ReadDelay(a) => {
+                self.registers[a as usize] = self.delaytimer;
+            }
+
+            Instruction::SetDelay(a) => {
+                self.delaytimer = self.registers[a as usize];
+            }
+            
+            Instruction::SkipIfPressed(x) => {
+                let val = self.registers[x as usize];
+                let key_pressed = self.check_key(val & 0xF);
+                if key_pressed {
+                    next += self.instruction_len(next);
+                }
+            }
+
+            Instruction::SkipIfNotPressed(x) => {
+                let val = self.registers[x as usize];
+                let key_pressed = self.check_key(val & 0xF);
+                if !key_pressed {
+                    next += self.instruction_len(next);
+                }
+            }
+
+            Instruction::WaitKey(x) => {
+                if !self.waiting_key {
+                    // only a key released after the instruction started counts
+                    self.keyboardhandler.take_released();
+                    self.waiting_key = true;
+                }
+                match self.keyboardhandler.take_released() {
+                    Some(key) => {
+                        self.registers[x as usize] = key & 0xF;
+                        self.waiting_key = false;
+                    }
+                    None => {
+                        next = pc;
+                        outcome = StepOutcome::WaitingForKey;
+                    }
+                }
+            }
+
+            Instruction::SetSound(x) => {
+                self.set_sound_timer(self.registers[x as usize]);
+            }
+
+            Instruction::SpriteDigit(x) => {
+                let val = self.registers[x as usize] & 0xF;
+                self.i = 5*val as u16;
+            }
+
+            Instruction::StoreRegisters(nb) => {
+                let last = self.i as usize + nb as usize;
+                if last >= self.memory.len() {
+                    return Err(oob(last));
+                }
+                for i in 0..nb+1 {
+                    self.memory[self.i as usize + i as usize] = self.registers[i as usize];
+                }
+                self.i = self.i.wrapping_add(self.quirks.memory_increment.amount(nb));
+            }
+            
+            Instruction::ReadRegisters(nb) => {
+                let last = self.i as usize + nb as usize;
+                if last >= self.memory.len() {
+                    return Err(oob(last));
+                }
+                for i in 0..nb+1 {
+                    self.registers[i as usize] = self.memory[self.i as usize + i as usize];
+                }
+                self.i = self.i.wrapping_add(self.quirks.memory_increment.amount(nb));
+            }
+
+            Instruction::StoreBCD(reg)  => {
+                let last = self.i as usize + 2;
+                if last >= self.memory.len() {
+                    return Err(oob(last));
+                }
+                let val = self.registers[reg as usize];
+                self.memory[self.i as usize] = val / 100;
+                self.memory[self.i as usize + 1] = (val / 10) % 10;
+                self.memory[self.i as usize + 2] = val % 10;
+            }
+
+            Instruction::Random(x, kk) => {
+                self.registers[x as usize] = self.random() & kk;
+            }
+
+            Instruction::ScrollDown(n) => {
+                self.framebuffer.scroll(0, n as isize, self.planes);
+            }
+
+            Instruction::ScrollUp(n) => {
+                self.framebuffer.scroll(0, -(n as isize), self.planes);
+            }
+
+            Instruction::ScrollRight => {
+                self.framebuffer.scroll(4, 0, self.planes);
+            }
+
+            Instruction::ScrollLeft => {
+                self.framebuffer.scroll(-4, 0, self.planes);
+            }
+
+            Instruction::Exit => {
+                next = pc;
+                outcome = StepOutcome::Exit;
+            }
+
+            Instruction::LowRes => {
+                self.framebuffer.set_hires(false);
+            }
+
+            Instruction::HighRes => {
+                self.framebuffer.set_hires(true);
+            }
+
+            Instruction::BigSpriteDigit(x) => {
+                let val = self.registers[x as usize] & 0xF;
+                self.i = (BIG_FONT_ADDR + 10*val as usize) as u16;
+            }
+
+            Instruction::StoreFlags(nb) => {
+                for i in 0..self.flags_up_to(nb) + 1 {
+                    self.rpl[i] = self.registers[i];
+                }
+            }
+
+            Instruction::ReadFlags(nb) => {
+                for i in 0..self.flags_up_to(nb) + 1 {
+                    self.registers[i] = self.rpl[i];
+                }
+            }
+
+            Instruction::SaveRange(x, y) => {
+                let (x, y) = (x as usize, y as usize);
+                let count = x.abs_diff(y) + 1;
+                let last = self.i as usize + count - 1;
+                if last >= self.memory.len() {
+                    return Err(oob(last));
+                }
+                for n in 0..count {
+                    let reg = if x <= y { x + n } else { x - n };
+                    self.memory[self.i as usize + n] = self.registers[reg];
+                }
+            }
+
+            Instruction::LoadRange(x, y) => {
+                let (x, y) = (x as usize, y as usize);
+                let count = x.abs_diff(y) + 1;
+                let last = self.i as usize + count - 1;
+                if last >= self.memory.len() {
+                    return Err(oob(last));
+                }
+                for n in 0..count {
+                    let reg = if x <= y { x + n } else { x - n };
+                    self.registers[reg] = self.memory[self.i as usize + n];
+                }
+            }
+
+            Instruction::LongI => {
+                if pc + 3 >= self.memory.len() {
+                    return Err(oob(pc + 3));
+                }
+                self.i = ((self.memory[pc + 2] as u16) << 8) | self.memory[pc + 3] as u16;
+                next = pc + 4;
+            }
+
+            Instruction::SelectPlane(n) => {
+                self.planes = n & 0x3;
+            }
+
+            Instruction::LoadAudio => {
+                let mut pattern = [0; 16];
+                for (n, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.memory[(self.i as usize + n) % self.memory.len()];
+                }
+                self.audio_pattern = Some(pattern);
+            }
+
+            Instruction::SetPitch(x) => {
+                self.pitch = self.registers[x as usize];
+            }
+
+            Instruction::Jump2(val) => {
+                let reg = if self.quirks.jump_uses_vx {
+                    self.registers[(val >> 8) as usize & 0xF]
+                } else {
+                    self.registers[0]
+                };
+                next = val as usize + reg as usize;
+            }
+        }
+
+        self.programcounter = next;
+        Ok(outcome)
+    }
+}
+
+/// The parts of `start..start + len` on a screen of `size` pixels: the part
+/// before the edge, then the part that wraps around when sprites don't clip.
+fn spans(start: usize, len: usize, size: usize, clip: bool) -> [(usize, usize); 2] {
+    let inside = len.min(size - start);
+    let wrapped = if clip { 0 } else { (len - inside).min(size) };
+    [(start, inside), (0, wrapped)]
+}
+
+/// Handlers that do nothing, for the tests of the modules built on the VM.
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) struct NoScreen;
+
+    impl DisplayHandler for NoScreen {
+        fn present(&mut self, _frame: &Framebuffer, _dirty: &[Rect]) {}
+    }
+
+    pub(crate) struct Silence;
+
+    impl SoundHandler for Silence {
+        fn start(&mut self, _tone: Tone) {}
+        fn stop(&mut self) {}
+    }
+
+    pub(crate) struct NoKeys;
+
+    impl KeyboardHandler for NoKeys {
+        fn is_pressed(&mut self, _key: u8) -> bool {
+            false
+        }
+        fn take_released(&mut self) -> Option<u8> {
+            None
+        }
+    }
+
+    pub(crate) struct Zero;
+
+    impl RandomHandler for Zero {
+        fn random(&mut self) -> u8 {
+            0
+        }
+    }
+
+    pub(crate) type TestVm = VM<NoScreen, NoKeys, Zero, Silence>;
+
+    pub(crate) fn test_vm(rom: &[u8], platform: Platform, quirks: Quirks) -> TestVm {
+        let mut vm = VM::new(NoScreen, NoKeys, Zero, Silence, platform, quirks);
+        vm.setmemory(rom.to_vec());
+        vm
+    }
+
+    /// Runs `opcode` once with VY then VX set, returns VX and VF afterwards.
+    fn alu(quirks: Quirks, opcode: u16, vx: u8, vy: u8) -> (u8, u8) {
+        let mut vm = VM::new(NoScreen, NoKeys, Zero, Silence, Platform::Chip8, quirks);
+        let x = (opcode >> 8) as usize & 0xF;
+        let y = (opcode >> 4) as usize & 0xF;
+        vm.registers[y] = vy;
+        vm.registers[x] = vx;
+        vm.setmemory(opcode.to_be_bytes().to_vec());
+        vm.process().unwrap();
+        (vm.registers[x], vm.registers[15])
+    }
+
+    fn check_table(quirks: Quirks, cases: &[(u16, u8, u8, u8, u8)]) {
+        for &(opcode, vx, vy, result, flag) in cases {
+            assert_eq!(
+                alu(quirks, opcode, vx, vy),
+                (result, flag),
+                "{:04X} with VX={:02X} VY={:02X}",
+                opcode,
+                vx,
+                vy
+            );
+        }
+    }
+
+    #[test]
+    fn alu_results_and_flags() {
+        // opcode, VX, VY, VX after, VF after
+        check_table(Quirks::CHIP_48, &[
+            (0x8124, 0x10, 0x20, 0x30, 0),
+            (0x8124, 0xFF, 0x01, 0x00, 1),
+            (0x8124, 0xFF, 0xFF, 0xFE, 1),
+            (0x8124, 0x80, 0x7F, 0xFF, 0),
+            (0x8125, 0x30, 0x10, 0x20, 1),
+            (0x8125, 0x10, 0x10, 0x00, 1),
+            (0x8125, 0x10, 0x30, 0xE0, 0),
+            (0x8125, 0x00, 0xFF, 0x01, 0),
+            (0x8127, 0x10, 0x30, 0x20, 1),
+            (0x8127, 0x10, 0x10, 0x00, 1),
+            (0x8127, 0x30, 0x10, 0xE0, 0),
+            (0x8127, 0xFF, 0x00, 0x01, 0),
+            // VY is ignored by the shifts without the quirk
+            (0x8126, 0x05, 0xAA, 0x02, 1),
+            (0x8126, 0xFE, 0xAA, 0x7F, 0),
+            (0x8126, 0x01, 0xAA, 0x00, 1),
+            (0x812E, 0x81, 0xAA, 0x02, 1),
+            (0x812E, 0x40, 0xAA, 0x80, 0),
+            (0x812E, 0xFF, 0xAA, 0xFE, 1),
+            (0x8121, 0x0F, 0xF0, 0xFF, 0),
+        ]);
+    }
+
+    #[test]
+    fn alu_flag_wins_when_x_is_vf() {
+        check_table(Quirks::CHIP_48, &[
+            (0x8F24, 0xFF, 0x03, 1, 1),
+            (0x8F24, 0x10, 0x20, 0, 0),
+            (0x8F25, 0x20, 0x10, 1, 1),
+            (0x8F25, 0x10, 0x20, 0, 0),
+            (0x8F27, 0x10, 0x20, 1, 1),
+            (0x8F27, 0x20, 0x10, 0, 0),
+            (0x8F26, 0x04, 0x00, 0, 0),
+            (0x8F26, 0x05, 0x00, 1, 1),
+            (0x8F2E, 0x81, 0x00, 1, 1),
+            (0x8F2E, 0x40, 0x00, 0, 0),
+        ]);
+    }
+
+    #[test]
+    fn alu_reads_vf_as_y_before_writing_the_flag() {
+        check_table(Quirks::CHIP_48, &[
+            (0x81F4, 0x10, 0xF0, 0x00, 1),
+            (0x81F5, 0x10, 0x01, 0x0F, 1),
+            (0x81F7, 0x01, 0x10, 0x0F, 1),
+        ]);
+    }
+
+    #[test]
+    fn alu_vip_quirks() {
+        check_table(Quirks::COSMAC_VIP, &[
+            // the shifts read VY
+            (0x8126, 0xFF, 0x05, 0x02, 1),
+            (0x812E, 0x00, 0x81, 0x02, 1),
+            // the logic ops reset VF, even when it is X
+            (0x8121, 0x0F, 0xF0, 0xFF, 0),
+            (0x8F22, 0xFF, 0x0F, 0x00, 0),
+            (0x8F23, 0x0F, 0xF0, 0x00, 0),
+        ]);
+    }
+
+    fn draw_quirks(clip_sprites: bool) -> Quirks {
+        Quirks { display_wait: false, clip_sprites, ..Quirks::COSMAC_VIP }
+    }
+
+    /// Loads the opcodes at 0x200 and executes them all.
+    fn run(quirks: Quirks, opcodes: &[u16]) -> VM<NoScreen, NoKeys, Zero, Silence> {
+        let mut vm = VM::new(NoScreen, NoKeys, Zero, Silence, Platform::Chip8, quirks);
+        vm.setmemory(opcodes.iter().flat_map(|opcode| opcode.to_be_bytes()).collect());
+        for _ in opcodes {
+            vm.process().unwrap();
+        }
+        vm
+    }
+
+    fn lit(vm: &VM<NoScreen, NoKeys, Zero, Silence>, x: usize, y: usize) -> bool {
+        vm.framebuffer.get(x, y) != 0
+    }
+
+    #[test]
+    fn draw_reports_collisions() {
+        // the 0 of the font at (8, 4)
+        let vm = run(draw_quirks(true), &[0x6008, 0x6104, 0xA000, 0xD015]);
+        assert_eq!(vm.registers[15], 0);
+        assert!(lit(&vm, 8, 4) && lit(&vm, 11, 8));
+        let vm = run(draw_quirks(true), &[0x6008, 0x6104, 0xA000, 0xD015, 0xD015]);
+        assert_eq!(vm.registers[15], 1);
+        assert!(!lit(&vm, 8, 4) && !lit(&vm, 11, 8));
+        // overlapping without erasing anything is not a collision
+        let vm = run(draw_quirks(true), &[0x6008, 0x6104, 0xA000, 0xD011, 0x6104, 0xA005, 0xD011]);
+        assert_eq!(vm.registers[15], 1);
+        let vm = run(draw_quirks(true), &[0x6008, 0x6104, 0xA000, 0xD011, 0x6105, 0xD011]);
+        assert_eq!(vm.registers[15], 0);
+    }
+
+    /// Draws a 0xFF, 0x81 sprite at (60, 31), across the bottom right corner.
+    fn draw_in_corner(clip_sprites: bool) -> VM<NoScreen, NoKeys, Zero, Silence> {
+        let mut vm = VM::new(NoScreen, NoKeys, Zero, Silence, Platform::Chip8, draw_quirks(clip_sprites));
+        vm.setmemory([0x603Cu16, 0x611F, 0xA300, 0xD012].iter().flat_map(|opcode| opcode.to_be_bytes()).collect());
+        vm.memory[0x300] = 0xFF;
+        vm.memory[0x301] = 0x81;
+        for _ in 0..4 {
+            vm.process().unwrap();
+        }
+        vm
+    }
+
+    #[test]
+    fn draw_clips_at_the_edges() {
+        let vm = draw_in_corner(true);
+        assert!(lit(&vm, 60, 31) && lit(&vm, 63, 31));
+        assert!(!lit(&vm, 0, 31) && !lit(&vm, 60, 0) && !lit(&vm, 3, 0));
+    }
+
+    #[test]
+    fn draw_wraps_at_the_edges() {
+        let vm = draw_in_corner(false);
+        assert!(lit(&vm, 60, 31) && lit(&vm, 63, 31));
+        assert!(lit(&vm, 0, 31) && lit(&vm, 3, 31));
+        assert!(lit(&vm, 60, 0) && !lit(&vm, 61, 0) && !lit(&vm, 0, 0) && lit(&vm, 3, 0));
+    }
+
+    #[test]
+    fn draw_wraps_the_starting_position() {
+        // (68, 35) is (4, 3), even when clipping
+        let vm = run(draw_quirks(true), &[0x6044, 0x6123, 0xA000, 0xD011]);
+        assert!(lit(&vm, 4, 3) && lit(&vm, 7, 3) && !lit(&vm, 8, 3));
+    }
+
+    #[test]
+    fn draw_reads_past_the_end_of_memory_from_the_start() {
+        // I = 0xFFF: the first row is the last byte of memory, the second the top of the 0 glyph
+        let mut vm = VM::new(NoScreen, NoKeys, Zero, Silence, Platform::Chip8, draw_quirks(true));
+        vm.setmemory([0x6000u16, 0x6100, 0xAFFF, 0xD012].iter().flat_map(|opcode| opcode.to_be_bytes()).collect());
+        vm.memory[0xFFF] = 0x81;
+        for _ in 0..4 {
+            vm.process().unwrap();
+        }
+        assert!(lit(&vm, 0, 0) && lit(&vm, 7, 0) && !lit(&vm, 1, 0));
+        assert!(lit(&vm, 0, 1) && lit(&vm, 3, 1) && !lit(&vm, 4, 1));
+    }
+
+    /// Remembers the dirty areas of every present.
+    struct Presents(Vec<Vec<Rect>>);
+
+    impl DisplayHandler for Presents {
+        fn present(&mut self, _frame: &Framebuffer, dirty: &[Rect]) {
+            self.0.push(dirty.to_vec());
+        }
+    }
+
+    #[test]
+    fn draws_are_presented_on_the_vblank() {
+        let mut vm = VM::new(Presents(Vec::new()), NoKeys, Zero, Silence, Platform::Chip8, draw_quirks(false));
+        // two digits next to each other, and one wrapping around the bottom right corner
+        vm.setmemory([0x6002u16, 0x610A, 0xD015, 0x6007, 0xD015, 0x603E, 0x611E, 0xD015].iter().flat_map(|opcode| opcode.to_be_bytes()).collect());
+        for _ in 0..8 {
+            vm.process().unwrap();
+        }
+        assert!(vm.displayhandler.0.is_empty());
+        vm.decrease_timer();
+        vm.decrease_timer();
+        assert_eq!(
+            vm.displayhandler.0,
+            [
+                vec![
+                    Rect::new(2, 10, 13, 5),
+                    Rect::new(62, 30, 2, 2),
+                    Rect::new(62, 0, 2, 3),
+                    Rect::new(0, 30, 6, 2),
+                    Rect::new(0, 0, 6, 3),
+                ],
+                vec![],
+            ]
+        );
+    }
+
+    /// Remembers every start and stop.
+    struct Buzzer(Vec<Option<Tone>>);
+
+    impl SoundHandler for Buzzer {
+        fn start(&mut self, tone: Tone) {
+            self.0.push(Some(tone));
+        }
+        fn stop(&mut self) {
+            self.0.push(None);
+        }
+    }
+
+    #[test]
+    fn the_buzzer_follows_the_sound_timer() {
+        let mut vm = VM::new(NoScreen, NoKeys, Zero, Buzzer(Vec::new()), Platform::Chip8, Quirks::COSMAC_VIP);
+        vm.tone.frequency = 1000;
+        // a beep of 2 frames, then one cut short by FX18 with 0
+        vm.setmemory([0x6002u16, 0xF018, 0x6003, 0xF018, 0x6000, 0xF018].iter().flat_map(|opcode| opcode.to_be_bytes()).collect());
+        vm.process().unwrap();
+        vm.process().unwrap();
+        let beep = Some(Tone { frequency: 1000, volume: 50 });
+        assert_eq!(vm.soundhandler.0, [beep]);
+        vm.decrease_timer();
+        vm.decrease_timer();
+        assert_eq!(vm.soundhandler.0, [beep, None]);
+        vm.decrease_timer();
+        for _ in 0..4 {
+            vm.process().unwrap();
+        }
+        assert_eq!(vm.soundhandler.0, [beep, None, beep, None]);
+    }
+
+    /// Runs the opcodes until one fails, returns the VM and the error.
+    fn fail(platform: Platform, opcodes: &[u16]) -> (TestVm, VmError) {
+        let rom: Vec<u8> = opcodes.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+        let mut vm = test_vm(&rom, platform, Quirks::COSMAC_VIP);
+        for _ in 0..100 {
+            if let Err(error) = vm.process() {
+                return (vm, error);
+            }
+        }
+        panic!("{:04X?} never failed", opcodes);
+    }
+
+    #[test]
+    fn errors_name_the_faulting_instruction() {
+        let (vm, error) = fail(Platform::Chip8, &[0x6007, 0x00EE]);
+        assert_eq!(error, VmError::StackUnderflow { pc: 0x202, opcode: 0x00EE });
+        assert_eq!((vm.pc(), vm.registers()[0]), (0x202, 7));
+
+        let (vm, error) = fail(Platform::Chip8, &[0x2200]);
+        assert_eq!(error, VmError::StackOverflow { pc: 0x200, opcode: 0x2200 });
+        assert_eq!((vm.pc(), vm.stack().len()), (0x200, STACK_SIZE));
+
+        let (vm, error) = fail(Platform::Chip8, &[0x1FFF]);
+        assert_eq!(error, VmError::InvalidPc { pc: 0xFFF });
+        assert_eq!((error.pc(), error.opcode()), (0xFFF, None));
+        assert_eq!(vm.pc(), 0xFFF);
+
+        // hires is SUPER-CHIP only, 5XY1 exists nowhere
+        let (vm, error) = fail(Platform::Chip8, &[0x00FF]);
+        assert_eq!(error, VmError::UnknownOpcode { pc: 0x200, opcode: 0x00FF });
+        assert_eq!((vm.pc(), vm.width()), (0x200, 64));
+        let (_, error) = fail(Platform::XoChip, &[0x6001, 0x5011]);
+        assert_eq!((error.pc(), error.opcode()), (0x202, Some(0x5011)));
+
+        let (vm, error) = fail(Platform::Chip8, &[0xAFFF, 0x6080, 0xF033]);
+        assert_eq!(error, VmError::MemoryOutOfBounds { pc: 0x204, opcode: 0xF033, address: 0x1001 });
+        assert_eq!((vm.pc(), vm.index(), vm.memory[0xFFF]), (0x204, 0xFFF, 0));
+        let (vm, error) = fail(Platform::Chip8, &[0xAFFF, 0xF255]);
+        assert_eq!(error, VmError::MemoryOutOfBounds { pc: 0x202, opcode: 0xF255, address: 0x1001 });
+        assert_eq!(vm.memory[0xFFF], 0);
+    }
+
+    /// An XO-CHIP VM with the opcodes at 0x200 and `data` at 0x300, `F000 NNNN` counting as one opcode.
+    fn xo_chip(opcodes: &[u16], data: &[u8]) -> TestVm {
+        let rom: Vec<u8> = opcodes.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+        let mut vm = test_vm(&rom, Platform::XoChip, Quirks::OCTO);
+        vm.memory[0x300..0x300 + data.len()].copy_from_slice(data);
+        vm
+    }
+
+    /// Executes everything up to the end of the opcodes.
+    fn run_all(vm: &mut TestVm, opcodes: usize) {
+        while (vm.pc() as usize) < 0x200 + 2 * opcodes {
+            vm.process().unwrap();
+        }
+    }
+
+    #[test]
+    fn sprites_go_to_the_selected_planes() {
+        // both planes: the first reads 0x300, the second 0x301
+        let opcodes = [0xA300, 0xF301, 0xD001];
+        let mut vm = xo_chip(&opcodes, &[0xC0, 0x40]);
+        run_all(&mut vm, opcodes.len());
+        assert_eq!([vm.framebuffer.get(0, 0), vm.framebuffer.get(1, 0), vm.framebuffer.get(2, 0)], [1, 3, 0]);
+        assert_eq!(vm.registers[15], 0);
+
+        // the second plane alone: erasing its pixel is a collision
+        let opcodes = [0xA300, 0xF301, 0xD001, 0xA301, 0xF201, 0xD001];
+        let mut vm = xo_chip(&opcodes, &[0xC0, 0x40]);
+        run_all(&mut vm, opcodes.len());
+        assert_eq!([vm.framebuffer.get(0, 0), vm.framebuffer.get(1, 0)], [1, 1]);
+        assert_eq!(vm.registers[15], 1);
+
+        // lighting the second plane over the first one isn't
+        let opcodes = [0xA300, 0xD001, 0xF201, 0xD001];
+        let mut vm = xo_chip(&opcodes, &[0xC0]);
+        run_all(&mut vm, opcodes.len());
+        assert_eq!([vm.framebuffer.get(0, 0), vm.framebuffer.get(1, 0)], [3, 3]);
+        assert_eq!(vm.registers[15], 0);
+
+        // no plane, nothing drawn
+        let opcodes = [0xA300, 0xF001, 0xD001];
+        let mut vm = xo_chip(&opcodes, &[0xC0]);
+        run_all(&mut vm, opcodes.len());
+        assert_eq!((vm.framebuffer.get(0, 0), vm.registers[15]), (0, 0));
+    }
+
+    #[test]
+    fn ranges_of_registers_go_both_ways() {
+        // v1 := 1, v2 := 2, v3 := 3, save v1 - v3 at 0x300, save v3 - v1 at 0x310
+        let opcodes = [0x6101, 0x6202, 0x6303, 0xA300, 0x5132, 0xA310, 0x5312];
+        let mut vm = xo_chip(&opcodes, &[]);
+        run_all(&mut vm, opcodes.len());
+        assert_eq!(vm.memory[0x300..0x303], [1, 2, 3]);
+        assert_eq!(vm.memory[0x310..0x313], [3, 2, 1]);
+        assert_eq!(vm.index(), 0x310);
+
+        // load v4 - v6 from 0x300, load v9 - v7 from 0x300
+        let opcodes = [0xA300, 0x5463, 0x5973];
+        let mut vm = xo_chip(&opcodes, &[7, 8, 9]);
+        run_all(&mut vm, opcodes.len());
+        assert_eq!(vm.registers[4..10], [7, 8, 9, 9, 8, 7]);
+        assert_eq!(vm.index(), 0x300);
+    }
+
+    #[test]
+    fn long_i_reaches_past_4k() {
+        // i := long 0x1234, sprite v0 v0 1
+        let opcodes = [0xF000, 0x1234, 0xD001];
+        let mut vm = xo_chip(&opcodes, &[]);
+        vm.memory[0x1234] = 0x80;
+        vm.process().unwrap();
+        assert_eq!((vm.pc(), vm.index()), (0x204, 0x1234));
+        vm.process().unwrap();
+        assert_eq!(vm.framebuffer.get(0, 0), 1);
+    }
+
+    #[test]
+    fn scroll_up_moves_the_selected_planes() {
+        // a pixel on both planes at (0, 5), then the first plane scrolls up 2
+        let opcodes = [0x6005, 0xA300, 0xF301, 0xD101, 0xF101, 0x00D2];
+        let mut vm = xo_chip(&opcodes, &[0x80, 0x80]);
+        run_all(&mut vm, opcodes.len());
+        assert_eq!([vm.framebuffer.get(0, 3), vm.framebuffer.get(0, 5)], [1, 2]);
+    }
+
+    /// Keys the test presses and releases by hand.
+    #[derive(Default)]
+    struct Keypad {
+        pressed: [bool; 16],
+        released: Option<u8>,
+    }
+
+    impl Keypad {
+        fn press(&mut self, key: u8) {
+            self.pressed[key as usize] = true;
+        }
+
+        fn release(&mut self, key: u8) {
+            self.pressed[key as usize] = false;
+            self.released = Some(key);
+        }
+    }
+
+    impl KeyboardHandler for Keypad {
+        fn is_pressed(&mut self, key: u8) -> bool {
+            self.pressed[key as usize & 0xF]
+        }
+        fn take_released(&mut self) -> Option<u8> {
+            self.released.take()
+        }
+    }
+
+    #[test]
+    fn wait_key_blocks_until_a_key_is_released() {
+        let mut vm = VM::new(NoScreen, Keypad::default(), Zero, Silence, Platform::Chip8, Quirks::COSMAC_VIP);
+        // delay := 5, buzzer := 3, v2 := key, then loop
+        vm.setmemory([0x6005u16, 0xF015, 0x6103, 0xF118, 0xF20A, 0x120A].iter().flat_map(|opcode| opcode.to_be_bytes()).collect());
+        for _ in 0..4 {
+            vm.process().unwrap();
+        }
+        // released before the instruction started, it doesn't count
+        vm.keyboardhandler.release(0xA);
+        assert_eq!(vm.process(), Ok(StepOutcome::WaitingForKey));
+        assert_eq!(vm.pc(), 0x208);
+
+        // the timers go on while it waits, holding a key isn't enough
+        vm.keyboardhandler.press(7);
+        for _ in 0..2 {
+            vm.decrease_timer();
+            assert_eq!(vm.process(), Ok(StepOutcome::WaitingForKey));
+        }
+        assert_eq!((vm.pc(), vm.registers[2]), (0x208, 0));
+        assert_eq!((vm.delay_timer(), vm.sound_timer()), (3, 1));
+
+        vm.keyboardhandler.release(7);
+        assert_eq!(vm.process(), Ok(StepOutcome::Continue));
+        assert_eq!((vm.pc(), vm.registers[2]), (0x20A, 7));
+    }
+
+    // v0 := 5, i := 0x300, call 0x20A, jump 0x208, sprite v0 v0 1, delay := v0, return
+    const STATEFUL: [u16; 8] = [0x6005, 0xA300, 0x220A, 0x0000, 0x1208, 0xD001, 0xF015, 0x00EE];
+
+    fn stateful(platform: Platform) -> TestVm {
+        let rom: Vec<u8> = STATEFUL.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+        let mut vm = test_vm(&rom, platform, Quirks::CHIP_48);
+        vm.memory[0x300] = 0x81;
+        vm
+    }
+
+    #[test]
+    fn save_states_round_trip() {
+        let mut vm = stateful(Platform::XoChip);
+        for _ in 0..5 {
+            vm.process().unwrap();
+        }
+        vm.memory[0x400] = 0x42;
+        let state = vm.save_state();
+        assert_eq!(state[..4], STATE_MAGIC);
+        assert_eq!((vm.pc(), vm.stack(), vm.delay_timer()), (0x20E, &[0x206][..], 5));
+
+        let mut other = stateful(Platform::XoChip);
+        other.load_state(&state).unwrap();
+        assert_eq!((other.pc(), other.stack(), other.delay_timer(), other.index()), (0x20E, &[0x206][..], 5, 0x300));
+        assert_eq!(other.registers(), vm.registers());
+        assert_eq!((other.framebuffer().get(5, 5), other.framebuffer().get(12, 5)), (1, 1));
+        assert_eq!(other.memory[0x400], 0x42);
+        assert_eq!(other.save_state(), state);
+    }
+
+    #[test]
+    fn save_states_of_other_roms_and_platforms_are_rejected() {
+        let state = stateful(Platform::Chip8).save_state();
+
+        let mut other_rom = stateful(Platform::Chip8);
+        other_rom.setmemory(vec![0x12, 0x00]);
+        let hash = other_rom.rom_hash();
+        assert!(matches!(other_rom.load_state(&state), Err(StateError::WrongRom { expected, .. }) if expected == hash));
+
+        let mut other_platform = stateful(Platform::SuperChip);
+        assert_eq!(
+            other_platform.load_state(&state),
+            Err(StateError::WrongPlatform { expected: Platform::SuperChip, found: Platform::Chip8 })
+        );
+    }
+
+    #[test]
+    fn broken_save_states_leave_the_vm_alone() {
+        let mut vm = stateful(Platform::Chip8);
+        vm.process().unwrap();
+        let state = vm.save_state();
+        let mut fresh = stateful(Platform::Chip8);
+        assert_eq!(fresh.load_state(&state[..state.len() - 1]), Err(StateError::Corrupted));
+        let mut longer = state.clone();
+        longer.push(0);
+        assert_eq!(fresh.load_state(&longer), Err(StateError::Corrupted));
+        let mut deep = state.clone();
+        deep[4 + 1 + 1 + 4 + 2 + 2 + 1 + 1 + 16] = 17; // stack depth
+        assert_eq!(fresh.load_state(&deep), Err(StateError::Corrupted));
+        let mut version = state.clone();
+        version[4] = STATE_VERSION + 1;
+        assert_eq!(fresh.load_state(&version), Err(StateError::UnsupportedVersion(STATE_VERSION + 1)));
+        assert_eq!(fresh.load_state(b"C8"), Err(StateError::BadMagic));
+        assert_eq!((fresh.pc(), fresh.registers()[0]), (0x200, 0));
+    }
+}
@@ -0,0 +1,66 @@
+use chip8_core::vm::KeyboardHandler;
+
+/// `FRAME:KEY[:FRAMES]`: hold KEY (hexadecimal) from FRAME for FRAMES frames, 1 by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Press {
+    pub frame: u64,
+    pub key: u8,
+    pub frames: u64,
+}
+
+impl Press {
+    pub fn parse(text: &str) -> Option<Press> {
+        let mut parts = text.split(':');
+        let frame = parts.next()?.parse().ok()?;
+        let key = u8::from_str_radix(parts.next()?, 16).ok().filter(|key| *key < 16)?;
+        let frames = match parts.next() {
+            Some(frames) => frames.parse().ok().filter(|frames| *frames > 0)?,
+            None => 1,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Press { frame, key, frames })
+    }
+}
+
+/// Keypad driven by a list of [`Press`], updated at the start of every frame.
+pub struct ScriptedKeyboard {
+    script: Vec<Press>,
+    pressed: [bool; 16],
+    released: Option<u8>,
+}
+
+impl ScriptedKeyboard {
+    pub fn new(script: Vec<Press>) -> Self {
+        ScriptedKeyboard {
+            script,
+            pressed: [false; 16],
+            released: None,
+        }
+    }
+
+    /// Presses and releases the keys as the script says for `frame`.
+    pub fn set_frame(&mut self, frame: u64) {
+        for key in 0..16u8 {
+            let down = self
+                .script
+                .iter()
+                .any(|press| press.key == key && (press.frame..press.frame + press.frames).contains(&frame));
+            if self.pressed[key as usize] && !down {
+                self.released = Some(key);
+            }
+            self.pressed[key as usize] = down;
+        }
+    }
+}
+
+impl KeyboardHandler for ScriptedKeyboard {
+    fn is_pressed(&mut self, key: u8) -> bool {
+        self.pressed[key as usize & 0xF]
+    }
+
+    fn take_released(&mut self) -> Option<u8> {
+        self.released.take()
+    }
+}
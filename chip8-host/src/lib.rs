@@ -0,0 +1,10 @@
+//! Runs ROMs without a screen, for regression tests and bug reports.
+//!
+//! Everything is deterministic: the keys come from a script, the random
+//! generator has a fixed seed and the timers tick every `ips / 60` instructions.
+
+pub mod image;
+pub mod keys;
+pub mod runner;
+pub mod sound;
+pub mod surface;
@@ -0,0 +1,396 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use chip8_core::assembler;
+use chip8_core::debugger::{Debugger, ReplAction, StopReason};
+use chip8_core::disasm::{Disassembler, Syntax};
+use chip8_core::phosphor::{Persistence, Phosphor};
+use chip8_core::platform::Platform;
+use chip8_core::quirks::Quirks;
+use chip8_core::random::{AnyRandom, VipRandom};
+
+use chip8_host::image;
+use chip8_host::keys::Press;
+use chip8_host::runner::{self, FPS};
+use chip8_host::sound;
+use chip8_host::surface::Surface;
+
+static USAGE: &str = "\
+usage: chip8-host run ROM [options]
+       chip8-host bench ROM [options]
+       chip8-host debug ROM [options]
+       chip8-host disasm ROM [--syntax octo|classic] [--platform NAME]
+       chip8-host asm SOURCE [-o OUT]
+
+bench runs the ROM as fast as possible (10000000 instructions unless --cycles
+is given) and prints the speed. debug reads debugger commands from stdin
+(help lists them), --out saves the screen when it quits. disasm prints the ROM as source, Octo syntax
+by default. asm assembles Octo source into OUT (SOURCE with a .ch8 extension by default).
+ROMs ending in .8o are assembled first.
+
+options:
+  --frames N          run N frames (60 by default)
+  --cycles N          run N instructions instead
+  --ips N             instructions per second (500 by default)
+  --platform NAME     chip8, schip or xochip (guessed from the extension by default)
+  --quirks NAME       vip, chip48, schip or octo (the platform's by default)
+  --seed N            seed of the random generator (1 by default)
+  --rng NAME          xorshift (the default, seeded by --seed) or vip:FILE to make
+                      random numbers like the COSMAC VIP, from page 0x01 of the
+                      dump of its interpreter in FILE (512 bytes, or the page)
+  --press F:K[:N]     hold key K (hex) from frame F for N frames, can be repeated
+  --out FILE          write the last frame to FILE, .pbm or .png
+  --every N           also write every Nth frame next to FILE, as FILE-NNNNN.ext
+  --scale N           size of a CHIP-8 pixel in the images (1 by default)
+  --persistence MODE  off, or (erased pixels keep their colour for one frame)
+                      or decay[:N] (erased pixels keep N% of their brightness
+                      every frame, 60 by default) to reduce flicker
+  --wav FILE          write the sound to FILE, 16-bit mono at 44100 Hz";
+
+struct RunOptions {
+    rom: PathBuf,
+    frames: u64,
+    cycles: Option<u64>,
+    ips: u64,
+    platform: Option<Platform>,
+    quirks: Option<Quirks>,
+    seed: u32,
+    /// Dump of the VIP interpreter, to use its random numbers.
+    vip_interpreter: Option<PathBuf>,
+    presses: Vec<Press>,
+    out: Option<PathBuf>,
+    every: Option<u64>,
+    scale: usize,
+    persistence: Persistence,
+    wav: Option<PathBuf>,
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", option))?;
+    value.parse().map_err(|_| format!("invalid value for {}: {}", option, value))
+}
+
+fn parse_run(args: &[String]) -> Result<RunOptions, String> {
+    let mut options = RunOptions {
+        rom: PathBuf::new(),
+        frames: 60,
+        cycles: None,
+        ips: 500,
+        platform: None,
+        quirks: None,
+        seed: 1,
+        vip_interpreter: None,
+        presses: Vec::new(),
+        out: None,
+        every: None,
+        scale: 1,
+        persistence: Persistence::Off,
+        wav: None,
+    };
+    let mut rom = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => options.frames = parse_number(arg, args.next())?,
+            "--cycles" => options.cycles = Some(parse_number(arg, args.next())?),
+            "--ips" => options.ips = parse_number::<u64>(arg, args.next())?.max(1),
+            "--seed" => options.seed = parse_number(arg, args.next())?,
+            "--every" => options.every = Some(parse_number::<u64>(arg, args.next())?.max(1)),
+            "--scale" => options.scale = parse_number::<usize>(arg, args.next())?.max(1),
+            "--platform" => {
+                let name = args.next().ok_or("--platform needs a value")?;
+                options.platform = Some(Platform::from_name(name).ok_or_else(|| format!("unknown platform {}", name))?);
+            }
+            "--quirks" => {
+                let name = args.next().ok_or("--quirks needs a value")?;
+                options.quirks = Some(Quirks::from_name(name).ok_or_else(|| format!("unknown quirks {}", name))?);
+            }
+            "--persistence" => {
+                let name = args.next().ok_or("--persistence needs a value")?;
+                options.persistence =
+                    Persistence::from_name(name).ok_or_else(|| format!("unknown persistence {}", name))?;
+            }
+            "--rng" => {
+                let rng = args.next().ok_or("--rng needs a value")?;
+                options.vip_interpreter = match rng.strip_prefix("vip:") {
+                    Some(path) => Some(PathBuf::from(path)),
+                    None if rng == "xorshift" => None,
+                    None => return Err(format!("unknown random generator {}", rng)),
+                };
+            }
+            "--press" => {
+                let press = args.next().ok_or("--press needs a value")?;
+                options.presses.push(Press::parse(press).ok_or_else(|| format!("invalid key press {}", press))?);
+            }
+            "--out" => options.out = Some(PathBuf::from(args.next().ok_or("--out needs a value")?)),
+            "--wav" => options.wav = Some(PathBuf::from(args.next().ok_or("--wav needs a value")?)),
+            option if option.starts_with("--") => return Err(format!("unknown option {}", option)),
+            path if rom.is_none() => rom = Some(PathBuf::from(path)),
+            extra => return Err(format!("unexpected argument {}", extra)),
+        }
+    }
+    options.rom = rom.ok_or("no ROM given")?;
+    if options.every.is_some() && options.out.is_none() {
+        return Err("--every needs --out".into());
+    }
+    Ok(options)
+}
+
+struct DisasmOptions {
+    rom: PathBuf,
+    syntax: Syntax,
+    platform: Option<Platform>,
+}
+
+fn parse_disasm(args: &[String]) -> Result<DisasmOptions, String> {
+    let mut options = DisasmOptions { rom: PathBuf::new(), syntax: Syntax::Octo, platform: None };
+    let mut rom = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--syntax" => {
+                options.syntax = match args.next().map(String::as_str) {
+                    Some("octo") => Syntax::Octo,
+                    Some("classic") => Syntax::Classic,
+                    Some(name) => return Err(format!("unknown syntax {}", name)),
+                    None => return Err("--syntax needs a value".into()),
+                }
+            }
+            "--platform" => {
+                let name = args.next().ok_or("--platform needs a value")?;
+                options.platform = Some(Platform::from_name(name).ok_or_else(|| format!("unknown platform {}", name))?);
+            }
+            option if option.starts_with("--") => return Err(format!("unknown option {}", option)),
+            path if rom.is_none() => rom = Some(PathBuf::from(path)),
+            extra => return Err(format!("unexpected argument {}", extra)),
+        }
+    }
+    options.rom = rom.ok_or("no ROM given")?;
+    Ok(options)
+}
+
+struct AsmOptions {
+    source: PathBuf,
+    out: PathBuf,
+}
+
+fn parse_asm(args: &[String]) -> Result<AsmOptions, String> {
+    let mut source = None;
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--out" => out = Some(PathBuf::from(args.next().ok_or_else(|| format!("{} needs a value", arg))?)),
+            option if option.starts_with("--") => return Err(format!("unknown option {}", option)),
+            path if source.is_none() => source = Some(PathBuf::from(path)),
+            extra => return Err(format!("unexpected argument {}", extra)),
+        }
+    }
+    let source: PathBuf = source.ok_or("no source given")?;
+    let out = out.unwrap_or_else(|| source.with_extension("ch8"));
+    Ok(AsmOptions { source, out })
+}
+
+/// `shot.png` becomes `shot-00030.png` for frame 30.
+fn numbered(path: &Path, frame: u64) -> PathBuf {
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("frame");
+    let name = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => format!("{}-{:05}.{}", stem, frame, ext),
+        None => format!("{}-{:05}", stem, frame),
+    };
+    path.with_file_name(name)
+}
+
+fn write_image(path: &Path, surface: &Surface, scale: usize) -> Result<(), String> {
+    let data = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("pbm") => image::pbm(surface, scale),
+        Some("png") => image::png(surface, scale),
+        _ => return Err(format!("{}: only .pbm and .png are supported", path.display())),
+    };
+    fs::write(path, data).map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+/// Sample rate of the files written by --wav.
+const SAMPLE_RATE: u32 = 44100;
+
+/// Instructions run by `bench` when --cycles isn't given.
+const BENCH_CYCLES: u64 = 10_000_000;
+
+/// Reads a ROM, assembling it first when it is Octo source.
+fn load_rom(path: &Path) -> Result<Vec<u8>, String> {
+    if path.extension().and_then(|ext| ext.to_str()) == Some("8o") {
+        let source = fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        return assembler::assemble(&source).map_err(|error| format!("{}:{}", path.display(), error));
+    }
+    fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+fn new_vm(options: &RunOptions) -> Result<runner::HostVm, String> {
+    let rom = load_rom(&options.rom)?;
+    let platform = options
+        .platform
+        .unwrap_or_else(|| Platform::from_path(&options.rom.to_string_lossy()));
+    let quirks = options.quirks.unwrap_or_else(|| platform.default_quirks());
+    let mut vm = runner::new_vm(rom, platform, quirks, options.seed, options.presses.clone());
+    if let Some(path) = &options.vip_interpreter {
+        let dump = fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let rng = VipRandom::from_dump(&dump)
+            .ok_or_else(|| format!("{}: not a dump of the VIP interpreter, it has {} bytes", path.display(), dump.len()))?;
+        vm.rng = AnyRandom::Vip(Box::new(rng));
+    }
+    if options.persistence != Persistence::Off {
+        vm.displayhandler.phosphor = Some(Phosphor::new(options.persistence, image::PALETTE));
+    }
+    Ok(vm)
+}
+
+fn report_error(end: &runner::RunEnd) -> ExitCode {
+    match end.error {
+        Some(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+        None => ExitCode::SUCCESS,
+    }
+}
+
+fn run(options: RunOptions) -> Result<ExitCode, String> {
+    let mut vm = new_vm(&options)?;
+    if options.wav.is_some() {
+        vm.soundhandler.record_samples(SAMPLE_RATE);
+    }
+
+    let per_frame = (options.ips / FPS).max(1);
+    let total = options.cycles.unwrap_or(options.frames * per_frame);
+    let end = runner::run(&mut vm, total, per_frame, |vm, frame| match (options.every, &options.out) {
+        (Some(every), Some(out)) if frame.is_multiple_of(every) => {
+            write_image(&numbered(out, frame), &vm.displayhandler, options.scale)
+        }
+        _ => Ok(()),
+    })?;
+
+    if let Some(out) = &options.out {
+        write_image(out, &vm.displayhandler, options.scale)?;
+    }
+    if let Some(path) = &options.wav {
+        let data = sound::wav(&vm.soundhandler.samples, SAMPLE_RATE);
+        fs::write(path, data).map_err(|error| format!("{}: {}", path.display(), error))?;
+    }
+    println!("cycles: {}", end.cycles);
+    println!("frames: {}", end.cycles / per_frame);
+    println!("hash: {:08X}", vm.displayhandler.hash());
+    for beep in &vm.soundhandler.beeps {
+        match beep.end {
+            Some(end) => println!("beep: frames {} to {}", beep.start, end - 1),
+            None => println!("beep: from frame {}", beep.start),
+        }
+    }
+    Ok(report_error(&end))
+}
+
+fn bench(options: RunOptions) -> Result<ExitCode, String> {
+    let mut vm = new_vm(&options)?;
+
+    let per_frame = (options.ips / FPS).max(1);
+    let total = options.cycles.unwrap_or(BENCH_CYCLES);
+    let start = Instant::now();
+    let end = runner::run(&mut vm, total, per_frame, |_, _| Ok(()))?;
+    let seconds = start.elapsed().as_secs_f64();
+
+    println!("cycles: {}", end.cycles);
+    println!("time: {:.3} s", seconds);
+    println!("speed: {:.1} M instructions/s", end.cycles as f64 / seconds / 1e6);
+    println!("hash: {:08X}", vm.displayhandler.hash());
+    Ok(report_error(&end))
+}
+
+fn debug(options: RunOptions) -> Result<ExitCode, String> {
+    let mut vm = new_vm(&options)?;
+    let mut debugger = Debugger::new((options.ips / FPS).max(1) as usize);
+
+    print!("{}", debugger.disassemble(&vm, vm.pc(), 0, 0));
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("(chip8) ");
+        io::stdout().flush().map_err(|error| error.to_string())?;
+        let Some(line) = lines.next() else {
+            println!();
+            break;
+        };
+        let line = line.map_err(|error| error.to_string())?;
+        let mut out = String::new();
+        let action = debugger.execute(&mut vm, &line, &mut out);
+        print!("{}", out);
+        match action {
+            ReplAction::Prompt => {}
+            ReplAction::Resume => {
+                let stop = debugger.run(&mut vm);
+                if stop != StopReason::Done {
+                    println!("{}", stop);
+                }
+                print!("{}", debugger.disassemble(&vm, vm.pc(), 0, 0));
+            }
+            ReplAction::Quit => break,
+        }
+    }
+
+    vm.present();
+    if let Some(out) = &options.out {
+        write_image(out, &vm.displayhandler, options.scale)?;
+    }
+    println!("hash: {:08X}", vm.displayhandler.hash());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn disasm(options: DisasmOptions) -> Result<ExitCode, String> {
+    let rom = load_rom(&options.rom)?;
+    let platform = options
+        .platform
+        .unwrap_or_else(|| Platform::from_path(&options.rom.to_string_lossy()));
+    print!("{}", Disassembler::new(&rom, platform).listing(options.syntax));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn asm(options: AsmOptions) -> Result<ExitCode, String> {
+    let path = &options.source;
+    let source = fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    let rom = match assembler::assemble(&source) {
+        Ok(rom) => rom,
+        Err(error) => {
+            // not a usage error, no need for the help
+            eprintln!("{}:{}", path.display(), error);
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    fs::write(&options.out, &rom).map_err(|error| format!("{}: {}", options.out.display(), error))?;
+    println!("{}: {} bytes", options.out.display(), rom.len());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("run") => parse_run(&args[1..]).and_then(run),
+        Some("bench") => parse_run(&args[1..]).and_then(bench),
+        Some("debug") => parse_run(&args[1..]).and_then(debug),
+        Some("disasm") => parse_disasm(&args[1..]).and_then(disasm),
+        Some("asm") => parse_asm(&args[1..]).and_then(asm),
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Some(command) => Err(format!("unknown command {}", command)),
+        None => Err("no command given".into()),
+    };
+    match result {
+        Ok(code) => code,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use chip8_core::debugger::Debugger;
+use chip8_core::error::VmError;
+use chip8_core::platform::Platform;
+use chip8_core::quirks::Quirks;
+use chip8_core::random::{AnyRandom, XorShiftRandom};
+use chip8_core::vm::{StepOutcome, VM};
+
+use crate::keys::{Press, ScriptedKeyboard};
+use crate::sound::BeepRecorder;
+use crate::surface::Surface;
+
+/// The timers tick, and the key script advances, 60 times per emulated second.
+pub const FPS: u64 = 60;
+
+pub type HostVm = VM<Surface, ScriptedKeyboard, AnyRandom, BeepRecorder>;
+
+/// Where a run stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunEnd {
+    /// Instructions executed, the failing one included.
+    pub cycles: u64,
+    /// The ROM executed `Exit`.
+    pub exited: bool,
+    pub error: Option<VmError>,
+}
+
+/// A VM with an in-memory screen, scripted keys, a seeded random generator and
+/// a buzzer that only records. Replace `rng` to use another generator.
+pub fn new_vm(rom: Vec<u8>, platform: Platform, quirks: Quirks, seed: u32, presses: Vec<Press>) -> HostVm {
+    let mut vm = VM::new(
+        Surface::new(),
+        ScriptedKeyboard::new(presses),
+        AnyRandom::XorShift(XorShiftRandom::new(seed)),
+        BeepRecorder::new(),
+        platform,
+        quirks,
+    );
+    vm.setmemory(rom);
+    vm
+}
+
+/// Executes `total` instructions, `per_frame` per frame, unless the ROM exits
+/// or fails first. `on_frame` is called at the start of every frame but the first.
+/// The screen is presented at the end when it changed since the last vblank,
+/// even in the middle of a frame.
+pub fn run(
+    vm: &mut HostVm,
+    total: u64,
+    per_frame: u64,
+    mut on_frame: impl FnMut(&HostVm, u64) -> Result<(), String>,
+) -> Result<RunEnd, String> {
+    let per_frame = per_frame.max(1);
+    // the debugger ticks the timers every `per_frame` steps
+    let mut debugger = Debugger::new(per_frame as usize);
+    let mut end = RunEnd { cycles: 0, exited: false, error: None };
+    while end.cycles < total {
+        if end.cycles.is_multiple_of(per_frame) {
+            let frame = end.cycles / per_frame;
+            if frame > 0 {
+                on_frame(vm, frame)?;
+            }
+            vm.keyboardhandler.set_frame(frame);
+            vm.soundhandler.set_frame(frame);
+        }
+        end.cycles += 1;
+        match debugger.step(vm) {
+            Ok(StepOutcome::Exit) => {
+                end.exited = true;
+                break;
+            }
+            Ok(_) => {}
+            Err(error) => {
+                end.error = Some(error);
+                break;
+            }
+        }
+    }
+    if !vm.framebuffer().dirty().is_empty() {
+        vm.present();
+    }
+    Ok(end)
+}
@@ -0,0 +1,103 @@
+use chip8_core::audio::{Audio, Synth};
+use chip8_core::vm::{SoundHandler, Tone};
+
+use crate::runner::FPS;
+
+/// A beep heard from the start of frame `start` to the end of frame `end - 1`,
+/// `end` is None while it still plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Beep {
+    pub start: u64,
+    pub end: Option<u64>,
+    pub tone: Tone,
+}
+
+/// Buzzer that writes down when it plays instead of making a sound, and
+/// optionally what it would sound like.
+#[derive(Default)]
+pub struct BeepRecorder {
+    frame: u64,
+    pub beeps: Vec<Beep>,
+    synth: Option<Synth>,
+    vblanks: u64,
+    /// Mono samples of every frame so far, see [`BeepRecorder::record_samples`].
+    pub samples: Vec<i16>,
+}
+
+impl BeepRecorder {
+    pub fn new() -> Self {
+        BeepRecorder::default()
+    }
+
+    /// Also renders the sound into `samples`, at `sample_rate` Hz.
+    pub fn record_samples(&mut self, sample_rate: u32) {
+        self.synth = Some(Synth::new(sample_rate));
+    }
+
+    /// The frame being run, for the next starts and stops.
+    pub fn set_frame(&mut self, frame: u64) {
+        self.frame = frame;
+    }
+}
+
+impl SoundHandler for BeepRecorder {
+    fn start(&mut self, tone: Tone) {
+        self.beeps.push(Beep { start: self.frame, end: None, tone });
+    }
+
+    fn stop(&mut self) {
+        if let Some(beep) = self.beeps.last_mut() {
+            beep.end = Some(self.frame + 1);
+        }
+    }
+
+    fn frame(&mut self, audio: &Audio) {
+        let Some(synth) = self.synth.as_mut() else {
+            return;
+        };
+        // the rounding of rate / FPS is spread over the frames
+        let rate = synth.sample_rate() as u64;
+        let count = ((self.vblanks + 1) * rate / FPS - self.vblanks * rate / FPS) as usize;
+        let start = self.samples.len();
+        self.samples.resize(start + count, 0);
+        synth.render(audio, &mut self.samples[start..]);
+        self.vblanks += 1;
+    }
+}
+
+/// 16-bit mono PCM WAV file.
+pub fn wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut out = Vec::with_capacity(44 + data_len as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes()); // size of the format chunk
+    out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    out.extend_from_slice(&1u16.to_le_bytes()); // mono
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // bytes per second
+    out.extend_from_slice(&2u16.to_le_bytes()); // bytes per sample
+    out.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        out.extend_from_slice(&sample.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wav_header() {
+        let wav = wav(&[1, -1], 8000);
+        assert_eq!(wav.len(), 48);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[4..8], &40u32.to_le_bytes());
+        assert_eq!(&wav[24..28], &8000u32.to_le_bytes());
+        assert_eq!(&wav[40..], &[4, 0, 0, 0, 1, 0, 0xFF, 0xFF]);
+    }
+}
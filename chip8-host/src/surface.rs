@@ -0,0 +1,93 @@
+use chip8_core::display::{Framebuffer, Rect};
+use chip8_core::phosphor::Phosphor;
+use chip8_core::vm::DisplayHandler;
+
+use crate::image::PALETTE;
+
+/// In-memory copy of the screen: one byte per pixel holding the plane bits, row-major.
+pub struct Surface {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    /// Colours shown for the pixels, [`PALETTE`] without persistence when None.
+    pub phosphor: Option<Phosphor>,
+}
+
+impl Surface {
+    pub fn new() -> Self {
+        Surface {
+            width: 64,
+            height: 32,
+            pixels: vec![0; 64 * 32],
+            phosphor: None,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// The colour of a pixel as 0xRRGGBB, after the phosphor if there is one
+    /// and it has seen a frame of this size.
+    pub fn color(&self, x: usize, y: usize) -> u32 {
+        match &self.phosphor {
+            Some(phosphor) if (phosphor.width(), phosphor.height()) == (self.width, self.height) => {
+                phosphor.color(x, y)
+            }
+            _ => PALETTE[self.get(x, y) as usize],
+        }
+    }
+
+    /// FNV-1a of the resolution and the pixels, the same screen always gives the same hash.
+    pub fn hash(&self) -> u32 {
+        let size = [self.width as u8, self.height as u8];
+        size.iter()
+            .chain(&self.pixels)
+            .fold(0x811C_9DC5, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x0100_0193))
+    }
+}
+
+impl Default for Surface {
+    fn default() -> Self {
+        Surface::new()
+    }
+}
+
+impl DisplayHandler for Surface {
+    fn present(&mut self, frame: &Framebuffer, dirty: &[Rect]) {
+        if (self.width, self.height) != (frame.width(), frame.height()) {
+            self.width = frame.width();
+            self.height = frame.height();
+            self.pixels = vec![0; self.width * self.height];
+        }
+        for rect in dirty {
+            for y in rect.y..rect.y + rect.height {
+                let (first, second) = (frame.row(0, y), frame.row(1, y));
+                for x in rect.x..rect.x + rect.width {
+                    let shift = self.width - 1 - x;
+                    let color = (first >> shift) & 1 | ((second >> shift) & 1) << 1;
+                    self.pixels[y * self.width + x] = color as u8;
+                }
+            }
+        }
+        if let Some(phosphor) = &mut self.phosphor {
+            phosphor.update(frame, dirty);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8_core::phosphor::Persistence;
+
+    use super::*;
+
+    #[test]
+    fn colors_before_the_first_present() {
+        let mut surface = Surface::new();
+        surface.phosphor = Some(Phosphor::new(Persistence::Or, PALETTE));
+        assert_eq!(surface.color(63, 31), PALETTE[0]);
+        surface.present(&Framebuffer::new(), &[]);
+        assert_eq!(surface.color(63, 31), PALETTE[0]);
+    }
+}
@@ -0,0 +1,62 @@
+//! The subcommands of the chip8-host binary, run as a user would.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use chip8_core::assembler;
+
+fn chip8_host(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chip8-host")).args(args).output().unwrap()
+}
+
+/// A file in a directory of its own for this test.
+fn scratch(test: &str, name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chip8-host-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn data(name: &str) -> String {
+    format!("{}/../data/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+#[test]
+fn asm_writes_the_rom() {
+    let out = scratch("asm", "bench.ch8");
+    let output = chip8_host(&["asm", &data("bench-sprites.8o"), "-o", out.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let source = fs::read_to_string(data("bench-sprites.8o")).unwrap();
+    assert_eq!(fs::read(&out).unwrap(), assembler::assemble(&source).unwrap());
+}
+
+#[test]
+fn asm_reports_where_errors_are() {
+    let source = scratch("asm-error", "broken.8o");
+    fs::write(&source, "v0 := 1\n: loop\n  jump nowhere\n").unwrap();
+    let output = chip8_host(&["asm", source.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with(&format!("{}:3:8: ", source.display())), "{}", stderr);
+    assert!(!source.with_extension("ch8").exists());
+}
+
+#[test]
+fn disasm_output_assembles_back() {
+    let listing = scratch("disasm", "ibm.8o");
+    let output = chip8_host(&["disasm", &data("ibm-logo.ch8")]);
+    assert!(output.status.success());
+    fs::write(&listing, &output.stdout).unwrap();
+    let output = chip8_host(&["asm", listing.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(listing.with_extension("ch8")).unwrap(), fs::read(data("ibm-logo.ch8")).unwrap());
+}
+
+#[test]
+fn usage_errors() {
+    let output = chip8_host(&["asm"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: no source given\n\nusage:"));
+    let output = chip8_host(&["asm", "a.8o", "-o"]);
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: -o needs a value\n"));
+}
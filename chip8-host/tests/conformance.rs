@@ -0,0 +1,354 @@
+//! Runs the test ROMs headlessly on every platform and quirk preset, and
+//! compares the screen with the snapshots in `tests/snapshots`.
+//!
+//! After a deliberate change, `UPDATE_SNAPSHOTS=1 cargo test -p chip8-host`
+//! rewrites the snapshots, check them with `git diff` before committing.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use chip8_core::assembler;
+use chip8_core::phosphor::{Persistence, Phosphor};
+use chip8_core::platform::Platform;
+use chip8_core::quirks::Quirks;
+
+use chip8_host::image::PALETTE;
+use chip8_host::runner::{self, HostVm};
+use chip8_host::sound::Beep;
+use chip8_host::surface::Surface;
+
+/// 10 seconds at 600 instructions per second, the slowest ROM is done after 2.
+const FRAMES: u64 = 600;
+const PER_FRAME: u64 = 10;
+
+const PLATFORMS: [(&str, Platform); 3] = [
+    ("chip8", Platform::Chip8),
+    ("schip", Platform::SuperChip),
+    ("xochip", Platform::XoChip),
+];
+
+const PRESETS: [(&str, Quirks); 4] = [
+    ("vip", Quirks::COSMAC_VIP),
+    ("chip48", Quirks::CHIP_48),
+    ("schip", Quirks::SUPER_CHIP),
+    ("octo", Quirks::OCTO),
+];
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// A ROM vendored in `data/`.
+fn binary(name: &str) -> Vec<u8> {
+    let path = manifest_dir().join("../data").join(name);
+    fs::read(&path).unwrap_or_else(|error| panic!("{}: {}", path.display(), error))
+}
+
+/// A test program of `tests/roms`, assembled after the shared `report.8o`.
+fn octo(name: &str) -> Vec<u8> {
+    let dir = manifest_dir().join("tests/roms");
+    let report = fs::read_to_string(dir.join("report.8o")).unwrap();
+    let test = fs::read_to_string(dir.join(name)).unwrap();
+    // a blank line between the two, so the line numbers of the test still start at 1
+    let source = format!("{}\n{}", report, test);
+    let offset = report.lines().count() + 1;
+    assembler::assemble(&source).unwrap_or_else(|error| match error.line.checked_sub(offset) {
+        Some(line) => panic!("{}:{}:{}: {}", name, line, error.column, error.message),
+        None => panic!("report.8o:{}", error),
+    })
+}
+
+/// One character per pixel: `.` when off, else the plane bits.
+fn screen(surface: &Surface) -> String {
+    let mut out = String::new();
+    for y in 0..surface.height {
+        for x in 0..surface.width {
+            out.push(match surface.get(x, y) {
+                0 => '.',
+                1 => '#',
+                color => char::from_digit(color as u32, 10).unwrap(),
+            });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Runs the ROM with every preset on `platforms`, returns the final screen of
+/// each run, keyed by `platform/preset`. `prepare` is called before each run.
+fn run_matrix(rom: &[u8], platforms: &[Platform], prepare: fn(&mut HostVm, Platform)) -> Vec<(String, String)> {
+    let mut results = Vec::new();
+    for (platform_name, platform) in PLATFORMS.iter().filter(|(_, platform)| platforms.contains(platform)) {
+        for (preset_name, quirks) in PRESETS {
+            let mut vm = runner::new_vm(rom.to_vec(), *platform, quirks, 1, Vec::new());
+            prepare(&mut vm, *platform);
+            let end = runner::run(&mut vm, FRAMES * PER_FRAME, PER_FRAME, |_, _| Ok(())).unwrap();
+            let mut text = String::new();
+            if let Some(error) = end.error {
+                writeln!(text, "error: {}", error).unwrap();
+            }
+            text.push_str(&screen(&vm.displayhandler));
+            results.push((format!("{}/{}", platform_name, preset_name), text));
+        }
+    }
+    results
+}
+
+/// Runs that gave the same screen share one entry: `== key key...` then the screen.
+fn serialize(results: &[(String, String)]) -> String {
+    let mut groups: Vec<(Vec<&str>, &str)> = Vec::new();
+    for (key, text) in results {
+        match groups.iter_mut().find(|(_, screen)| screen == text) {
+            Some((keys, _)) => keys.push(key),
+            None => groups.push((vec![key], text)),
+        }
+    }
+    let mut out = String::new();
+    for (keys, text) in groups {
+        writeln!(out, "== {}", keys.join(" ")).unwrap();
+        out.push_str(text);
+    }
+    out
+}
+
+fn deserialize(snapshot: &str) -> Vec<(String, String)> {
+    let mut results = Vec::new();
+    let mut keys: Vec<String> = Vec::new();
+    let mut text = String::new();
+    for line in snapshot.lines() {
+        if let Some(header) = line.strip_prefix("== ") {
+            results.extend(keys.drain(..).map(|key| (key, text.clone())));
+            keys = header.split_whitespace().map(String::from).collect();
+            text.clear();
+        } else {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+    results.extend(keys.drain(..).map(|key| (key, text.clone())));
+    results
+}
+
+/// The rows that differ, expected above actual, with a `^` under every different pixel.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut out = String::new();
+    let mut pixels = 0;
+    for row in 0..expected.len().max(actual.len()) {
+        let want = expected.get(row).copied().unwrap_or("");
+        let got = actual.get(row).copied().unwrap_or("");
+        if want == got {
+            continue;
+        }
+        let width = want.len().max(got.len());
+        let marks: String = (0..width)
+            .map(|x| if want.as_bytes().get(x) == got.as_bytes().get(x) { ' ' } else { '^' })
+            .collect();
+        pixels += marks.matches('^').count();
+        writeln!(out, "  {:>3} - {}", row, want).unwrap();
+        writeln!(out, "      + {}", got).unwrap();
+        writeln!(out, "        {}", marks.trim_end()).unwrap();
+    }
+    writeln!(out, "  {} pixel(s) differ", pixels).unwrap();
+    out
+}
+
+fn check(name: &str, rom: Vec<u8>, platforms: &[Platform]) {
+    check_with(name, rom, platforms, |_, _| {});
+}
+
+fn check_with(name: &str, rom: Vec<u8>, platforms: &[Platform], prepare: fn(&mut HostVm, Platform)) {
+    let path = manifest_dir().join("tests/snapshots").join(format!("{}.txt", name));
+    let results = run_matrix(&rom, platforms, prepare);
+
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, serialize(&results)).unwrap();
+        return;
+    }
+
+    let snapshot = fs::read_to_string(&path).unwrap_or_default();
+    let expected = deserialize(&snapshot);
+    let mut failures = String::new();
+    for (key, text) in &results {
+        match expected.iter().find(|(expected_key, _)| expected_key == key) {
+            Some((_, expected_text)) if expected_text == text => {}
+            Some((_, expected_text)) => {
+                writeln!(failures, "{} {}: the screen differs from the snapshot", name, key).unwrap();
+                failures.push_str(&diff(expected_text, text));
+            }
+            None => writeln!(failures, "{} {}: no snapshot, run with UPDATE_SNAPSHOTS=1", name, key).unwrap(),
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures);
+}
+
+const ALL: [Platform; 3] = [Platform::Chip8, Platform::SuperChip, Platform::XoChip];
+
+#[test]
+fn chip8_logo() {
+    check("chip8-logo", binary("chip8-logo.ch8"), &ALL);
+}
+
+#[test]
+fn ibm_logo() {
+    check("ibm-logo", binary("ibm-logo.ch8"), &ALL);
+}
+
+// The Timendus test suite, fetched into `data/` by `make roms`. Every check
+// shows OK or a tick when it passed, so the snapshots can be read.
+
+#[test]
+#[ignore = "needs the Timendus ROMs in data/, run `make roms`"]
+fn timendus_corax_plus() {
+    check("3-corax+", binary("3-corax+.ch8"), &ALL);
+}
+
+#[test]
+#[ignore = "needs the Timendus ROMs in data/, run `make roms`"]
+fn timendus_flags() {
+    check("4-flags", binary("4-flags.ch8"), &ALL);
+}
+
+#[test]
+#[ignore = "needs the Timendus ROMs in data/, run `make roms`"]
+fn timendus_quirks() {
+    // the ROM skips its menu when 0x1FF holds the platform to test against
+    check_with("5-quirks", binary("5-quirks.ch8"), &ALL, |vm, platform| {
+        vm.memory[0x1FF] = match platform {
+            Platform::Chip8 => 1,
+            Platform::XoChip => 3,
+            // SUPER-CHIP 1.1, not the modern one
+            _ => 4,
+        };
+    });
+}
+
+#[test]
+fn opcodes() {
+    check("opcodes", octo("opcodes.8o"), &ALL);
+}
+
+#[test]
+fn flags() {
+    check("flags", octo("flags.8o"), &ALL);
+}
+
+#[test]
+fn quirks() {
+    check("quirks", octo("quirks.8o"), &ALL);
+}
+
+#[test]
+fn hires() {
+    check("hires", octo("hires.8o"), &[Platform::SuperChip, Platform::XoChip]);
+}
+
+#[test]
+fn schip() {
+    check("schip", octo("schip.8o"), &[Platform::SuperChip, Platform::XoChip]);
+}
+
+#[test]
+fn beeps_follow_the_sound_timer() {
+    let rom = assembler::assemble(
+        "
+        : main
+          v0 := 3  buzzer := v0
+          v0 := 10  delay := v0
+        : wait
+          v0 := delay
+          if v0 != 0 then jump wait
+          v0 := 2  buzzer := v0
+        : end
+          jump end
+        ",
+    )
+    .unwrap();
+    let mut vm = runner::new_vm(rom, Platform::Chip8, Quirks::COSMAC_VIP, 1, Vec::new());
+    runner::run(&mut vm, 20 * PER_FRAME, PER_FRAME, |_, _| Ok(())).unwrap();
+    let tone = vm.tone;
+    assert_eq!(
+        vm.soundhandler.beeps,
+        [Beep { start: 0, end: Some(3), tone }, Beep { start: 10, end: Some(12), tone }]
+    );
+}
+
+#[test]
+fn xo_chip_audio_plays_the_pattern_at_the_pitch() {
+    let rom = assembler::assemble(
+        "
+        : main
+          i := pattern  audio
+          v0 := 112  pitch := v0
+          v0 := 2  buzzer := v0
+        : end
+          jump end
+        : pattern
+          0xF0 0 0 0  0 0 0 0  0 0 0 0  0 0 0 0
+        ",
+    )
+    .unwrap();
+    let mut vm = runner::new_vm(rom, Platform::XoChip, Quirks::OCTO, 1, Vec::new());
+    // 8000 bits per second at pitch 112, one sample per bit
+    vm.soundhandler.record_samples(8000);
+    runner::run(&mut vm, 3 * PER_FRAME, PER_FRAME, |_, _| Ok(())).unwrap();
+    let samples = &vm.soundhandler.samples;
+    let high = i16::MAX / 2;
+    assert_eq!(samples.len(), 133 + 133 + 134);
+    assert!(samples[..4].iter().all(|sample| *sample == high));
+    assert!(samples[4..128].iter().all(|sample| *sample == -high));
+    assert!(samples[128..132].iter().all(|sample| *sample == high));
+    // the buzzer stops after 2 frames
+    assert!(samples[266..].iter().all(|sample| *sample == 0));
+}
+
+/// The colour of the top left pixel at the start of every frame, when a dot is
+/// drawn there then erased two frames later. The first draw waits for the
+/// vblank on the VIP.
+fn blinking_dot(persistence: Persistence) -> Vec<u32> {
+    let rom = assembler::assemble(
+        "
+        : main
+          i := dot  sprite v0 v0 1
+          v1 := 2  delay := v1
+        : wait
+          v1 := delay
+          if v1 != 0 then jump wait
+          sprite v0 v0 1
+        : end
+          jump end
+        : dot
+          0x80
+        ",
+    )
+    .unwrap();
+    let mut vm = runner::new_vm(rom, Platform::Chip8, Quirks::COSMAC_VIP, 1, Vec::new());
+    vm.displayhandler.phosphor = Some(Phosphor::new(persistence, PALETTE));
+    let mut colors = Vec::new();
+    runner::run(&mut vm, 7 * PER_FRAME, PER_FRAME, |vm, _| {
+        colors.push(vm.displayhandler.color(0, 0));
+        Ok(())
+    })
+    .unwrap();
+    colors
+}
+
+#[test]
+fn persistence_keeps_erased_pixels_on_screen() {
+    let (white, black) = (PALETTE[1], PALETTE[0]);
+    assert_eq!(blinking_dot(Persistence::Off), [black, white, white, black, black, black]);
+    assert_eq!(blinking_dot(Persistence::Or), [black, white, white, white, black, black]);
+    assert_eq!(
+        blinking_dot(Persistence::Decay(50)),
+        [black, white, white, 0x7F7F7F, 0x3F3F3F, 0x1F1F1F]
+    );
+}
+
+#[test]
+fn diff_points_at_the_pixels() {
+    let diff = diff("..#.\n....\n", "..#.\n.#..\n");
+    assert_eq!(diff, "    1 - ....\n      + .#..\n         ^\n  1 pixel(s) differ\n");
+}
@@ -0,0 +1,183 @@
+// Shows the CHIP-8 screen on the VESA framebuffer: scaled, centred and framed.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use chip8_core::display::{Framebuffer, Rect};
+use chip8_core::phosphor::{Persistence, Phosphor};
+use chip8_core::vm::DisplayHandler;
+
+use crate::profan::Vesa;
+
+/// Colour of the VESA screen around the border.
+const OUTSIDE: u32 = 0x000000;
+
+/// How the screen looks, colours are 0xRRGGBB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayConfig {
+    /// Screen pixels per CHIP-8 pixel, None for the largest that fits.
+    /// Too large a scale is reduced until the screen fits.
+    pub scale: Option<usize>,
+    /// Background, first plane, second plane, both planes. Only the first
+    /// two are used outside of XO-CHIP.
+    pub palette: [u32; 4],
+    pub border: u32,
+    /// In screen pixels, 0 for none.
+    pub border_width: usize,
+    /// Keeps erased pixels on screen a bit longer, against flicker.
+    pub persistence: Persistence,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            scale: None,
+            palette: [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555],
+            border: 0x444444,
+            border_width: 4,
+            persistence: Persistence::Off,
+        }
+    }
+}
+
+/// Where the CHIP-8 screen is drawn, in screen pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    scale: usize,
+    x: usize,
+    y: usize,
+    width: usize, // CHIP-8 pixels
+    height: usize,
+    screen_width: usize,
+    screen_height: usize,
+}
+
+impl Layout {
+    /// Width of the border on the left, top, right and bottom, thinner where
+    /// the screen leaves no room for it.
+    fn borders(&self, border_width: usize) -> (usize, usize, usize, usize) {
+        (
+            border_width.min(self.x),
+            border_width.min(self.y),
+            border_width.min(self.screen_width.saturating_sub(self.x + self.width * self.scale)),
+            border_width.min(self.screen_height.saturating_sub(self.y + self.height * self.scale)),
+        )
+    }
+
+    /// The screen and its border.
+    fn outer(&self, border_width: usize) -> (usize, usize, usize, usize) {
+        let (left, top, right, bottom) = self.borders(border_width);
+        (
+            self.x - left,
+            self.y - top,
+            left + self.width * self.scale + right,
+            top + self.height * self.scale + bottom,
+        )
+    }
+}
+
+pub struct VesaDisplay {
+    config: DisplayConfig,
+    layout: Option<Layout>, // None until the first frame
+    phosphor: Phosphor,
+    /// The scaled screen, row-major, copied to the VESA framebuffer once per frame.
+    scaled: Vec<u32>,
+}
+
+impl VesaDisplay {
+    pub fn new(config: DisplayConfig) -> Self {
+        let phosphor = Phosphor::new(config.persistence, config.palette);
+        VesaDisplay { config, layout: None, phosphor, scaled: Vec::new() }
+    }
+
+    /// The largest scale that fits (or the configured one if smaller), centred.
+    fn layout(&self, vesa: &Vesa, width: usize, height: usize) -> Layout {
+        let border = self.config.border_width;
+        let room = |screen: usize, size: usize| (screen.saturating_sub(2 * border) / size).max(1);
+        let fits = room(vesa.width, width).min(room(vesa.height, height));
+        let scale = self.config.scale.map_or(fits, |scale| scale.clamp(1, fits));
+        Layout {
+            scale,
+            x: vesa.width.saturating_sub(width * scale) / 2,
+            y: vesa.height.saturating_sub(height * scale) / 2,
+            width,
+            height,
+            screen_width: vesa.width,
+            screen_height: vesa.height,
+        }
+    }
+
+    fn draw_border(&self, vesa: &Vesa, layout: &Layout) {
+        let (left, top, right, bottom) = layout.borders(self.config.border_width);
+        let (x, y, width, height) = layout.outer(self.config.border_width);
+        vesa.fill(x, y, width, top, self.config.border);
+        vesa.fill(x, y + height - bottom, width, bottom, self.config.border);
+        vesa.fill(x, y, left, height, self.config.border);
+        vesa.fill(x + width - right, y, right, height, self.config.border);
+    }
+
+    /// Scales the colours of `rect` into `scaled`, the off-screen copy of the screen.
+    fn render(scaled: &mut [u32], phosphor: &Phosphor, layout: &Layout, rect: &Rect) {
+        let scale = layout.scale;
+        let stride = layout.width * scale;
+        let (left, right) = (rect.x * scale, (rect.x + rect.width) * scale);
+        for y in rect.y..rect.y + rect.height {
+            let top = y * scale * stride;
+            let (first, rest) = scaled[top..top + scale * stride].split_at_mut(stride);
+            for x in rect.x..rect.x + rect.width {
+                first[x * scale..(x + 1) * scale].fill(phosphor.color(x, y));
+            }
+            // the other screen rows of this CHIP-8 row are the same
+            for row in rest.chunks_mut(stride) {
+                row[left..right].copy_from_slice(&first[left..right]);
+            }
+        }
+    }
+
+    /// Copies `rect` of the off-screen copy to the VESA framebuffer.
+    fn blit(&self, vesa: &Vesa, layout: &Layout, rect: &Rect) {
+        let scale = layout.scale;
+        let stride = layout.width * scale;
+        let start = rect.y * scale * stride + rect.x * scale;
+        vesa.blit(
+            layout.x + rect.x * scale,
+            layout.y + rect.y * scale,
+            rect.width * scale,
+            rect.height * scale,
+            &self.scaled[start..],
+            stride,
+        );
+    }
+}
+
+impl DisplayHandler for VesaDisplay {
+    fn present(&mut self, frame: &Framebuffer, dirty: &[Rect]) {
+        self.phosphor.update(frame, dirty);
+        let vesa = Vesa::get();
+        let layout = self.layout(&vesa, frame.width(), frame.height());
+        if self.layout != Some(layout) {
+            // the resolution changed (or this is the first frame): wipe the
+            // old screen, then draw all of the new one
+            if let Some(old) = self.layout {
+                let (x, y, width, height) = old.outer(self.config.border_width);
+                vesa.fill(x, y, width, height, OUTSIDE);
+            }
+            self.draw_border(&vesa, &layout);
+            self.scaled = vec![0; frame.width() * frame.height() * layout.scale * layout.scale];
+            let all = Rect::new(0, 0, frame.width(), frame.height());
+            VesaDisplay::render(&mut self.scaled, &self.phosphor, &layout, &all);
+            self.blit(&vesa, &layout, &all);
+            self.layout = Some(layout);
+            return;
+        }
+        // one copy per frame, around everything that changed
+        let changed = self.phosphor.changed();
+        let Some(area) = changed.iter().copied().reduce(|area, rect| area.union(&rect)) else {
+            return;
+        };
+        for rect in changed {
+            VesaDisplay::render(&mut self.scaled, &self.phosphor, &layout, rect);
+        }
+        self.blit(&vesa, &layout, &area);
+    }
+}
@@ -0,0 +1,51 @@
+// The CHIP-8 keypad, held down or not from the PS/2 scancodes ProfanOS gives us.
+
+use chip8_core::vm::KeyboardHandler;
+
+use chip8_cli::keymap::Keymap;
+
+/// Scancode of the Escape key, used to quit.
+pub const SCANCODE_ESCAPE: u8 = 0x01;
+
+/// Bit set on the scancode when the key is released (scancode set 1).
+const RELEASED: u8 = 0x80;
+
+pub struct BasicKeyboardHandler {
+    pub keymap: Keymap,
+    status: [bool; 16],
+    released: Option<u8>,
+}
+
+impl BasicKeyboardHandler {
+    pub fn new(keymap: Keymap) -> Self {
+        BasicKeyboardHandler {
+            keymap,
+            status: [false; 16],
+            released: None,
+        }
+    }
+
+    /// Updates the keypad from a raw scancode, returns the CHIP-8 key if it is bound.
+    pub fn handle_scancode(&mut self, scancode: u8) -> Option<u8> {
+        let pressed = scancode & RELEASED == 0;
+        let key = self.keymap.key_for(scancode & !RELEASED)?;
+        if !pressed && self.status[key as usize] {
+            self.released = Some(key);
+        }
+        self.status[key as usize] = pressed;
+        Some(key)
+    }
+}
+
+impl KeyboardHandler for BasicKeyboardHandler {
+    fn is_pressed(&mut self, key: u8) -> bool {
+        if key >= 16 {
+            return false
+        }
+        self.status[key as usize]
+    }
+
+    fn take_released(&mut self) -> Option<u8> {
+        self.released.take()
+    }
+}
@@ -0,0 +1,307 @@
+#![no_std]
+#![no_main]
+#![allow(unused)]
+
+extern crate rust_profanos;
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::format;
+use rust_profanos::libs::std;
+use rust_profanos::libs::std::fs::File;
+use rust_profanos::libs::std::io::Read;
+use rust_profanos::libs as libs;
+use rust_profanos::{print, println};
+use rust_profanos::utilities as utilities;
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+
+use alloc::{vec, vec::Vec};
+
+pub mod panichandler;
+
+pub mod profan;
+
+pub mod keyboard;
+
+pub mod speaker;
+
+pub mod display;
+
+use chip8_core::vm::{
+    DisplayHandler, KeyboardHandler,
+    RandomHandler, SoundHandler,
+    StepOutcome, Tracer, VM,
+};
+use chip8_core::insts::Instruction;
+use chip8_core::platform::Platform;
+use chip8_core::scheduler::Scheduler;
+use chip8_core::random::{AnyRandom, VipRandom, XorShiftRandom};
+use chip8_core::debugger::{Debugger, ReplAction};
+use profan::ProfanClock;
+use keyboard::{BasicKeyboardHandler, SCANCODE_ESCAPE};
+use speaker::PcSpeaker;
+use display::{DisplayConfig, VesaDisplay};
+use chip8_cli::{Command, USAGE};
+
+/// Prints every instruction before it runs, enabled with `--trace`.
+struct PrintTracer;
+
+impl Tracer for PrintTracer {
+    fn trace(&mut self, pc: u16, instruction: &Instruction) {
+        println!("{:03X}: {}", pc, instruction);
+    }
+}
+
+static FPS: u32 = 60;
+
+// F1-F4 save the state in slots 1-4, F5-F8 load them back
+const SCANCODE_F1: u8 = 0x3B;
+const SCANCODE_F5: u8 = 0x3F;
+const SLOTS: u8 = 4;
+// F9 halves the speed, F10 doubles it
+const SCANCODE_F9: u8 = 0x43;
+const SCANCODE_F10: u8 = 0x44;
+// F12 breaks into the debugger
+const SCANCODE_F12: u8 = 0x58;
+
+/// Save states live next to the ROM: `game.ch8` gets `game.ch8.st1` to `game.ch8.st4`.
+fn slot_path(rom: &str, slot: u8) -> String {
+    format!("{}.st{}", rom, slot)
+}
+
+fn save_slot<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(vm: &VM<T, T2, T3, T4>, rom: &str, slot: u8) {
+    let path = slot_path(rom, slot);
+    if profan::write_file(&path, &vm.save_state()) {
+        println!("State saved to {}", path);
+    } else {
+        println!("Could not write {}", path);
+    }
+}
+
+fn load_slot<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(vm: &mut VM<T, T2, T3, T4>, rom: &str, slot: u8) {
+    let path = slot_path(rom, slot);
+    if !profan::file_exists(&path) {
+        println!("No state in slot {}", slot);
+        return;
+    }
+    let mut file = File::open(&path).unwrap();
+    let mut content: Vec<u8> = vec![0; file.metadata().unwrap().len()];
+    file.read(&mut content);
+    match vm.load_state(&content) {
+        Ok(()) => println!("State loaded from {}", path),
+        Err(error) => println!("Could not load {} : {}", path, error),
+    }
+}
+
+/// Changes the speed of the VM, and how many steps the debugger takes per timer tick with it.
+fn set_speed(scheduler: &mut Scheduler, debugger: &mut Debugger, ips: u32, fps: u32) {
+    scheduler.set_ips(ips.max(1));
+    scheduler.set_fps(fps);
+    debugger.steps_per_tick = (scheduler.ips() / scheduler.fps()).max(1) as usize;
+    println!("{} instructions per second, {} frames per second", scheduler.ips(), scheduler.fps());
+}
+
+/// `ips [N]` and `fps [N]`, in decimal, on top of the commands of the debugger.
+/// Returns false for the other commands.
+fn speed_command(line: &str, scheduler: &mut Scheduler, debugger: &mut Debugger) -> bool {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or("");
+    if command != "ips" && command != "fps" {
+        return false;
+    }
+    let (ips, fps) = match words.next().map(str::parse::<u32>) {
+        None => (scheduler.ips(), scheduler.fps()),
+        Some(Ok(n)) if command == "ips" => (n, scheduler.fps()),
+        Some(Ok(n)) => (scheduler.ips(), n),
+        Some(Err(_)) => {
+            println!("usage: {} [N]", command);
+            return true;
+        }
+    };
+    set_speed(scheduler, debugger, ips, fps);
+    true
+}
+
+/// The random numbers of the VIP, from a dump of its interpreter.
+fn read_vip_interpreter(path: &str) -> Option<VipRandom> {
+    let mut file = File::open(path)?;
+    let mut content: Vec<u8> = vec![0; file.metadata()?.len()];
+    file.read(&mut content);
+    VipRandom::from_dump(&content)
+}
+
+/// Reads debugger commands from the console until one resumes or quits.
+fn repl<T: DisplayHandler, T2: KeyboardHandler, T3: RandomHandler, T4: SoundHandler>(
+    debugger: &mut Debugger,
+    scheduler: &mut Scheduler,
+    vm: &mut VM<T, T2, T3, T4>,
+) -> ReplAction {
+    print!("{}", debugger.disassemble(vm, vm.pc(), 0, 0));
+    loop {
+        print!("(chip8) ");
+        let line = profan::read_line();
+        if speed_command(&line, scheduler, debugger) {
+            continue;
+        }
+        let mut out = String::new();
+        let action = debugger.execute(vm, &line, &mut out);
+        print!("{}", out);
+        // the timers may not have ticked, show what the steps drew
+        vm.present();
+        if action != ReplAction::Prompt {
+            return action;
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let options = match chip8_cli::parse(args.get(1..).unwrap_or_default()) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("chip8 {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(message) => {
+            println!("error: {}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if !profan::file_exists(&options.rom) {
+        println!("error: no such file {}", options.rom);
+        std::process::exit(1);
+    }
+
+    println!("Hello from Rust and Chip-8 !");
+
+    let mut config = DisplayConfig { scale: options.scale, persistence: options.persistence, ..DisplayConfig::default() };
+    if let Some(bg) = options.bg {
+        config.palette[0] = bg;
+    }
+    if let Some(fg) = options.fg {
+        config.palette[1] = fg;
+    }
+    if let Some(plane2) = options.plane2 {
+        config.palette[2] = plane2;
+    }
+    if let Some(plane3) = options.plane3 {
+        config.palette[3] = plane3;
+    }
+    let display_handler = VesaDisplay::new(config);
+    let keyboard_handler = BasicKeyboardHandler::new(options.keymap);
+    let random_handler = match &options.vip_interpreter {
+        Some(path) => match read_vip_interpreter(path) {
+            Some(rng) => AnyRandom::Vip(Box::new(rng)),
+            None => {
+                println!("error: {} is not a dump of the VIP interpreter", path);
+                std::process::exit(1);
+            }
+        },
+        // a different game every time, unless a seed is given
+        None => AnyRandom::XorShift(XorShiftRandom::new(options.seed.unwrap_or(profan::now_ms() as u32))),
+    };
+
+    println!("{}", options.rom);
+
+    let platform = options.platform.unwrap_or_else(|| Platform::from_path(&options.rom));
+    let quirks = options.quirks.unwrap_or_else(|| platform.default_quirks());
+
+    let mut vm = VM::new(
+        display_handler,
+        keyboard_handler,
+        random_handler,
+        PcSpeaker,
+        platform,
+        quirks,
+    );
+
+    if options.trace {
+        vm.tracer = Some(Box::new(PrintTracer));
+    }
+
+    let mut file = File::open(&options.rom).unwrap();
+
+    let mut content: Vec<u8> = vec![0; file.metadata().unwrap().len()];
+
+    file.read(&mut content);
+
+    vm.setmemory(content);
+
+    let mut scheduler = Scheduler::new(options.ips, FPS);
+    let mut clock = ProfanClock;
+
+    let mut debugger = Debugger::new((options.ips / FPS).max(1) as usize);
+    let mut paused = options.paused;
+
+    'main: loop {
+        if paused {
+            // no beeping while the console waits for commands
+            vm.soundhandler.stop();
+            if repl(&mut debugger, &mut scheduler, &mut vm) == ReplAction::Quit {
+                break 'main;
+            }
+            if vm.sound_timer() > 0 {
+                vm.soundhandler.start(vm.tone);
+            }
+            paused = false;
+        }
+
+        while let Some(scancode) = profan::scancode() {
+            if scancode == SCANCODE_ESCAPE {
+                break 'main;
+            }
+            if scancode == SCANCODE_F12 {
+                paused = true;
+                continue;
+            }
+            if scancode == SCANCODE_F9 || scancode == SCANCODE_F10 {
+                let ips = if scancode == SCANCODE_F9 { scheduler.ips() / 2 } else { scheduler.ips().saturating_mul(2) };
+                let fps = scheduler.fps();
+                set_speed(&mut scheduler, &mut debugger, ips, fps);
+                continue;
+            }
+            if (SCANCODE_F1..SCANCODE_F1 + SLOTS).contains(&scancode) {
+                save_slot(&vm, &options.rom, scancode - SCANCODE_F1 + 1);
+                continue;
+            }
+            if (SCANCODE_F5..SCANCODE_F5 + SLOTS).contains(&scancode) {
+                load_slot(&mut vm, &options.rom, scancode - SCANCODE_F5 + 1);
+                continue;
+            }
+            vm.keyboardhandler.handle_scancode(scancode);
+        }
+
+        if paused {
+            continue;
+        }
+
+        match scheduler.run_frame_until(&mut vm, &mut clock, |vm| debugger.is_breakpoint(vm.pc())) {
+            Ok(None) => {
+                println!("Breakpoint at {:03X}", vm.pc());
+                paused = true;
+            }
+            Ok(Some(StepOutcome::Exit)) => break,
+            Ok(Some(_)) => {}
+            Err(error) => {
+                println!("Chip-8 error : {}", error);
+                if !options.debug {
+                    break;
+                }
+                // keep the machine around to inspect it
+                paused = true;
+            }
+        }
+    }
+
+    // the sound timer may still be running
+    vm.soundhandler.stop();
+    println!("Lets exit now !");
+}
\ No newline at end of file
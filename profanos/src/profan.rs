@@ -0,0 +1,176 @@
+// Bindings to the parts of the ProfanOS libc that rust_profanos doesn't wrap yet.
+
+use core::arch::asm;
+use core::ffi::c_void;
+
+use alloc::ffi::CString;
+use alloc::string::String;
+
+use chip8_core::scheduler::TimeSource;
+
+// number of `syscall_sc_get` in the ProfanOS syscall table
+const SYS_SC_GET: u32 = 29;
+// number of `syscall_vesa_info`, its argument says what to return
+const SYS_VESA_INFO: u32 = 18;
+const VESA_WIDTH: u32 = 0;
+const VESA_HEIGHT: u32 = 1;
+const VESA_PITCH: u32 = 2;
+const VESA_FRAMEBUFFER: u32 = 3;
+
+// clock() counts milliseconds on ProfanOS
+const CLOCKS_PER_SEC: u64 = 1000;
+
+extern "C" {
+    fn clock() -> i32;
+    fn usleep(usec: u32) -> i32;
+    fn fopen(path: *const u8, mode: *const u8) -> *mut c_void;
+    fn fwrite(buffer: *const u8, size: usize, count: usize, stream: *mut c_void) -> usize;
+    fn fclose(stream: *mut c_void) -> i32;
+    fn getchar() -> i32;
+}
+
+/// Next scancode from the keyboard history, None when no key was hit since the last call.
+pub fn scancode() -> Option<u8> {
+    let ret: u32;
+    unsafe {
+        asm!(
+            "int 0x80",
+            inout("eax") SYS_SC_GET => ret,
+        );
+    }
+    match ret {
+        0 => None,
+        sc => Some(sc as u8),
+    }
+}
+
+fn vesa_info(what: u32) -> u32 {
+    let ret: u32;
+    unsafe {
+        asm!(
+            "push ebx",
+            "mov ebx, {0:e}",
+            "int 0x80",
+            "pop ebx",
+            in(reg) what,
+            inout("eax") SYS_VESA_INFO => ret,
+        );
+    }
+    ret
+}
+
+/// The VESA framebuffer, 32 bits per pixel.
+pub struct Vesa {
+    pub width: usize,
+    pub height: usize,
+    pitch: usize, // in pixels
+    pixels: *mut u32,
+}
+
+impl Vesa {
+    pub fn get() -> Vesa {
+        Vesa {
+            width: vesa_info(VESA_WIDTH) as usize,
+            height: vesa_info(VESA_HEIGHT) as usize,
+            pitch: vesa_info(VESA_PITCH) as usize,
+            pixels: vesa_info(VESA_FRAMEBUFFER) as *mut u32,
+        }
+    }
+
+    /// Fills a rectangle of screen pixels with `color`, the part outside of the screen is ignored.
+    pub fn fill(&self, x: usize, y: usize, width: usize, height: usize, color: u32) {
+        let width = width.min(self.width.saturating_sub(x));
+        if width == 0 {
+            return;
+        }
+        for line in y..(y + height).min(self.height) {
+            unsafe {
+                let start = self.pixels.add(line * self.pitch + x);
+                core::slice::from_raw_parts_mut(start, width).fill(color);
+            }
+        }
+    }
+
+    /// Copies `width` x `height` pixels to (x, y), one row of `pixels` every
+    /// `stride` pixels, the part outside of the screen is ignored.
+    pub fn blit(&self, x: usize, y: usize, width: usize, height: usize, pixels: &[u32], stride: usize) {
+        let width = width.min(self.width.saturating_sub(x));
+        if width == 0 {
+            return;
+        }
+        for (line, row) in (y..(y + height).min(self.height)).zip(pixels.chunks(stride)) {
+            unsafe {
+                let start = self.pixels.add(line * self.pitch + x);
+                core::slice::from_raw_parts_mut(start, width).copy_from_slice(&row[..width]);
+            }
+        }
+    }
+}
+
+/// Reads a line from the console, without the final newline.
+pub fn read_line() -> String {
+    let mut line = String::new();
+    loop {
+        let c = unsafe { getchar() };
+        if c < 0 || c == b'\n' as i32 {
+            return line;
+        }
+        line.push(c as u8 as char);
+    }
+}
+
+/// Milliseconds since boot.
+pub fn now_ms() -> u64 {
+    let ticks = unsafe { clock() } as u32 as u64;
+    ticks * 1000 / CLOCKS_PER_SEC
+}
+
+pub fn sleep_us(us: u64) {
+    unsafe {
+        usleep(us as u32);
+    }
+}
+
+/// The ProfanOS clock, used to pace the VM.
+pub struct ProfanClock;
+
+impl TimeSource for ProfanClock {
+    fn now_us(&mut self) -> u64 {
+        now_ms() * 1000
+    }
+
+    fn sleep_us(&mut self, us: u64) {
+        sleep_us(us);
+    }
+}
+
+/// Whether `path` can be opened for reading.
+pub fn file_exists(path: &str) -> bool {
+    let Ok(path) = CString::new(path) else {
+        return false;
+    };
+    unsafe {
+        let file = fopen(path.as_ptr() as *const u8, c"r".as_ptr() as *const u8);
+        if file.is_null() {
+            return false;
+        }
+        fclose(file);
+    }
+    true
+}
+
+/// Replaces the content of `path` with `data`, rust_profanos can only read files.
+pub fn write_file(path: &str, data: &[u8]) -> bool {
+    let Ok(path) = CString::new(path) else {
+        return false;
+    };
+    unsafe {
+        let file = fopen(path.as_ptr() as *const u8, c"w".as_ptr() as *const u8);
+        if file.is_null() {
+            return false;
+        }
+        let written = fwrite(data.as_ptr(), 1, data.len(), file);
+        fclose(file);
+        written == data.len()
+    }
+}
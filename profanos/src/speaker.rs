@@ -0,0 +1,52 @@
+// The PC speaker, driven by channel 2 of the PIT. QEMU plays it when started with
+// `-audiodev pa,id=snd0 -machine pcspk-audiodev=snd0`.
+
+use core::arch::asm;
+
+use chip8_core::vm::{SoundHandler, Tone};
+
+/// Frequency of the PIT input clock, in Hz.
+const PIT_FREQUENCY: u32 = 1_193_182;
+
+const PIT_CHANNEL2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+/// Channel 2, low then high byte of the divisor, square wave.
+const PIT_SQUARE_WAVE: u8 = 0xB6;
+
+const SPEAKER_PORT: u16 = 0x61;
+/// Gate of the PIT channel 2 and speaker enable.
+const SPEAKER_ON: u8 = 0x03;
+
+fn outb(port: u16, value: u8) {
+    unsafe {
+        asm!("out dx, al", in("dx") port, in("al") value);
+    }
+}
+
+fn inb(port: u16) -> u8 {
+    let value: u8;
+    unsafe {
+        asm!("in al, dx", in("dx") port, out("al") value);
+    }
+    value
+}
+
+/// The speaker can only be on or off, a volume of 0 mutes it.
+pub struct PcSpeaker;
+
+impl SoundHandler for PcSpeaker {
+    fn start(&mut self, tone: Tone) {
+        if tone.volume == 0 || tone.frequency == 0 {
+            return;
+        }
+        let divisor = (PIT_FREQUENCY / tone.frequency).clamp(1, 0xFFFF) as u16;
+        outb(PIT_COMMAND, PIT_SQUARE_WAVE);
+        outb(PIT_CHANNEL2, divisor as u8);
+        outb(PIT_CHANNEL2, (divisor >> 8) as u8);
+        outb(SPEAKER_PORT, inb(SPEAKER_PORT) | SPEAKER_ON);
+    }
+
+    fn stop(&mut self) {
+        outb(SPEAKER_PORT, inb(SPEAKER_PORT) & !SPEAKER_ON);
+    }
+}
@@ -1,2 +0,0 @@
-pub mod vm;
-pub mod insts;